@@ -0,0 +1,194 @@
+// Picking a quote from a huge pack: generates a pack of a million quotes and compares
+// the time and peak memory of reading it a quote at a time, through the pack cache
+// and with the code from before streaming, which loads the whole pack. That code is
+// given with KOTOFETCH_BASELINE.
+//
+//   KOTOFETCH_BASELINE=/path/to/old/kotofetch cargo bench --bench pool
+#[cfg(unix)]
+mod bench {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{BufWriter, Read, Write};
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    const QUOTES: usize = 1_000_000;
+    const RUNS: usize = 3;
+    const SEED: &str = "42";
+
+    fn generate(path: &Path) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        for i in 0..QUOTES {
+            writeln!(out, "[[quote]]")?;
+            writeln!(out, "japanese = \"千里の道も一歩から {i}\"")?;
+            writeln!(
+                out,
+                "translation = \"A journey of a thousand miles, step {i}\""
+            )?;
+            writeln!(out, "romaji = \"senri no michi mo ippo kara\"")?;
+            writeln!(out, "source = \"Generated\"\n")?;
+        }
+        out.flush()
+    }
+
+    struct Run {
+        time: Duration,
+        // kilobytes
+        peak: u64,
+        stdout: String,
+    }
+
+    // The kotofetch from before streaming, from $KOTOFETCH_BASELINE. It isn't built from
+    // git here: a shallow clone, a release tarball or rewritten history would each give
+    // a different baseline, or none.
+    fn baseline() -> Result<PathBuf, String> {
+        let path = env::var_os("KOTOFETCH_BASELINE")
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .ok_or(
+                "set KOTOFETCH_BASELINE to a kotofetch built from the commit before \
+                 \"Stream huge packs instead of loading the whole pool\", for example:\n  \
+                 git worktree add /tmp/kotofetch-baseline <commit>\n  \
+                 cargo build --release --manifest-path /tmp/kotofetch-baseline/Cargo.toml\n  \
+                 KOTOFETCH_BASELINE=/tmp/kotofetch-baseline/target/release/kotofetch \
+                 cargo bench --bench pool",
+            )?;
+        if path.is_file() {
+            Ok(path)
+        } else {
+            Err(format!(
+                "KOTOFETCH_BASELINE: {} is not a file",
+                path.display()
+            ))
+        }
+    }
+
+    // Run kotofetch and reap it with wait4, which gives its peak memory. It runs from
+    // the bench directory with nothing of the user's environment: no config, quotes,
+    // state or KOTOFETCH_* variables.
+    #[allow(clippy::zombie_processes)]
+    fn run(binary: &Path, dir: &Path, args: &[&str]) -> Run {
+        let start = Instant::now();
+        let mut child = Command::new(binary)
+            .args(args)
+            .current_dir(dir)
+            .env_clear()
+            .env("HOME", dir)
+            .env("XDG_CONFIG_HOME", dir.join("config"))
+            .env("XDG_CONFIG_DIRS", dir.join("system"))
+            .env("XDG_STATE_HOME", dir.join("state"))
+            .env("XDG_DATA_HOME", dir.join("data"))
+            .env("XDG_DATA_DIRS", dir.join("system"))
+            .env("XDG_CACHE_HOME", dir.join("cache"))
+            .env("KOTOFETCH_CACHE_DIR", dir.join("cache"))
+            .env("NO_COLOR", "1")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to run kotofetch");
+        // read first so a long output can't fill the pipe and block kotofetch
+        let mut stdout = String::new();
+        if let Some(mut out) = child.stdout.take() {
+            out.read_to_string(&mut stdout).ok();
+        }
+        let mut status = 0;
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        let pid = unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut usage) };
+        let time = start.elapsed();
+        assert!(pid > 0, "wait4 failed");
+        assert!(
+            libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0,
+            "kotofetch {args:?} failed"
+        );
+
+        // ru_maxrss is in bytes on macOS and kilobytes elsewhere
+        let peak = usage.ru_maxrss as u64;
+        let peak = if cfg!(target_os = "macos") {
+            peak / 1024
+        } else {
+            peak
+        };
+        Run { time, peak, stdout }
+    }
+
+    // Best time and highest peak of a few runs.
+    fn measure(name: &str, binary: &Path, dir: &Path, args: &[&str], before: impl Fn()) -> String {
+        let mut time = Duration::MAX;
+        let mut peak = 0;
+        let mut stdout = String::new();
+        for _ in 0..RUNS {
+            before();
+            let run = run(binary, dir, args);
+            time = time.min(run.time);
+            peak = peak.max(run.peak);
+            stdout = run.stdout;
+        }
+        println!(
+            "{name:<28} {:>9.3} s {:>9.1} MB",
+            time.as_secs_f64(),
+            peak as f64 / 1024.0
+        );
+        stdout
+    }
+
+    pub fn main() {
+        let baseline = match baseline() {
+            Ok(baseline) => baseline,
+            Err(e) => {
+                eprintln!("no baseline to compare with: {e}");
+                std::process::exit(1);
+            }
+        };
+        let dir = std::env::temp_dir().join(format!("kotofetch-bench-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("failed to create the bench directory");
+        let pack = dir.join("huge.toml");
+        generate(&pack).expect("failed to generate the pack");
+        let size = fs::metadata(&pack).map(|m| m.len()).unwrap_or(0);
+        println!(
+            "{QUOTES} quotes, {:.1} MB pack, best of {RUNS} runs\n",
+            size as f64 / 1e6
+        );
+        println!("{:<28} {:>11} {:>12}", "", "time", "peak memory");
+
+        let modes = format!("--modes={}", pack.display());
+        let pick = |extra: &[&'static str]| {
+            let mut args = vec![modes.as_str(), "--seed", SEED];
+            args.extend(extra);
+            args
+        };
+        let clear_cache = || {
+            fs::remove_dir_all(dir.join("cache")).ok();
+        };
+
+        let current = Path::new(env!("CARGO_BIN_EXE_kotofetch"));
+        let streamed = measure(
+            "streamed (--no-cache)",
+            current,
+            &dir,
+            &pick(&["--no-cache"]),
+            || {},
+        );
+        measure("cache build", current, &dir, &pick(&[]), clear_cache);
+        let cached = measure("cache hit", current, &dir, &pick(&[]), || {});
+        // its cache would hide the load, like the cache hit above
+        let args = pick(&["--no-cache"]);
+        measure("whole pack (baseline)", &baseline, &dir, &args, || {});
+
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(
+            streamed, cached,
+            "streamed and cached packs picked different quotes for the same seed"
+        );
+    }
+}
+
+#[cfg(unix)]
+fn main() {
+    bench::main();
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("the pool benchmark needs wait4 and only runs on Unix");
+}
@@ -0,0 +1,467 @@
+// Binary cache of parsed quote packs, so a random quote can be read without parsing
+// every pack on each run.
+//
+// One cache file per pack file, named after a hash of the pack path, in three segments:
+//   header, 56 bytes
+//     magic "KFCACHE5"
+//     source mtime (u64 seconds, u32 nanoseconds), size (u64) and content hash (u64)
+//     quote count (u32), position of the offsets segment (u64) and total weight (f64,
+//     0 when no quote has a weight)
+//   quotes, from byte 56, one record per quote
+//     japanese, translation, romaji, source, id, season and time, each a u32 length and
+//     UTF-8 bytes (u32::MAX for a missing field), then the months (u32, bit n for
+//     month n, 0 when unset) and the weight (f64, 0 when unset)
+//   offsets, 16 bytes per quote up to the end of the file
+//     the position of its record (u64) and the weight of the quotes up to it (f64), so
+//     a weighted pick is a binary search
+// All numbers are little endian. The offsets come last so the cache can be written
+// while the pack is read. A file whose length doesn't match its header is rebuilt.
+use crate::diagnostic::Level;
+use crate::quotes::{Quote, is_command_pack, stream_file};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 8] = b"KFCACHE5";
+// magic, mtime, size, hash, count, offsets, weight
+const HEADER_LEN: u64 = 8 + 12 + 8 + 8 + 4 + 8 + 8;
+// offset and cumulative weight
+const ENTRY_LEN: u64 = 16;
+const NONE: u32 = u32::MAX;
+
+// Directory holding the cache ($KOTOFETCH_CACHE_DIR or ~/.cache/kotofetch/packs).
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("KOTOFETCH_CACHE_DIR").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    let mut path = dirs::cache_dir().unwrap_or_else(std::env::temp_dir);
+    path.push("kotofetch/packs");
+    path
+}
+
+pub const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+// 64-bit FNV-1a, stable across builds unlike std's hasher.
+pub fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut hash = FNV_OFFSET;
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(hash),
+            n => hash = fnv1a(hash, &buf[..n]),
+        }
+    }
+}
+
+fn cache_file(pack: &Path) -> PathBuf {
+    let pack = fs::canonicalize(pack).unwrap_or_else(|_| pack.to_path_buf());
+    let hash = fnv1a(FNV_OFFSET, pack.as_os_str().to_string_lossy().as_bytes());
+    cache_dir().join(format!("{hash:016x}.bin"))
+}
+
+struct Header {
+    mtime: (u64, u32),
+    size: u64,
+    hash: u64,
+    count: u32,
+    offsets: u64,
+    weight: f64,
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_header(r: &mut impl Read) -> io::Result<Header> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a pack cache",
+        ));
+    }
+    Ok(Header {
+        mtime: (read_u64(r)?, read_u32(r)?),
+        size: read_u64(r)?,
+        hash: read_u64(r)?,
+        count: read_u32(r)?,
+        offsets: read_u64(r)?,
+        weight: f64::from_bits(read_u64(r)?),
+    })
+}
+
+fn write_header(w: &mut impl Write, h: &Header) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&h.mtime.0.to_le_bytes())?;
+    w.write_all(&h.mtime.1.to_le_bytes())?;
+    w.write_all(&h.size.to_le_bytes())?;
+    w.write_all(&h.hash.to_le_bytes())?;
+    w.write_all(&h.count.to_le_bytes())?;
+    w.write_all(&h.offsets.to_le_bytes())?;
+    w.write_all(&h.weight.to_le_bytes())
+}
+
+// The quotes of one cached pack. Quotes are read from disk one at a time.
+pub struct PackIndex {
+    file: File,
+    count: usize,
+    offsets: u64,
+    weight: f64,
+}
+
+impl PackIndex {
+    fn new(file: File, header: &Header) -> Self {
+        PackIndex {
+            file,
+            count: header.count as usize,
+            offsets: header.offsets,
+            weight: header.weight,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    // Total weight of the quotes, None if none of them has a weight.
+    pub fn weight(&self) -> Option<f64> {
+        (self.weight > 0.0).then_some(self.weight)
+    }
+
+    fn entry(&self, index: usize) -> Option<(u64, f64)> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(self.offsets + ENTRY_LEN * index as u64))
+            .ok()?;
+        Some((
+            read_u64(&mut file).ok()?,
+            f64::from_bits(read_u64(&mut file).ok()?),
+        ))
+    }
+
+    // Index of the quote found at `point` along the quotes' weights.
+    pub fn pick(&self, point: f64) -> Option<usize> {
+        let (mut low, mut high) = (0, self.count.checked_sub(1)?);
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.entry(mid)?.1 > point {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        Some(low)
+    }
+
+    pub fn get(&self, index: usize) -> Option<Quote> {
+        if index >= self.count {
+            return None;
+        }
+        let (offset, _) = self.entry(index)?;
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        read_quote(&mut file)
+    }
+
+    // Go through the quotes in order, reading the file once.
+    pub fn scan(&self, f: &mut dyn FnMut(usize, &Quote)) {
+        let mut file = &self.file;
+        if file.seek(SeekFrom::Start(HEADER_LEN)).is_err() {
+            return;
+        }
+        let mut reader = io::BufReader::new(file);
+        for index in 0..self.count {
+            match read_quote(&mut reader) {
+                Some(quote) => f(index, &quote),
+                None => return,
+            }
+        }
+    }
+}
+
+fn read_quote(r: &mut impl Read) -> Option<Quote> {
+    let mut field = || -> Option<Option<String>> {
+        let len = read_u32(r).ok()?;
+        if len == NONE {
+            return Some(None);
+        }
+        let mut buf = vec![0; len as usize];
+        r.read_exact(&mut buf).ok()?;
+        String::from_utf8(buf).ok().map(Some)
+    };
+    Some(Quote {
+        japanese: field()??,
+        translation: field()?,
+        romaji: field()?,
+        source: field()?,
+        id: field()?,
+        season: field()?,
+        time: field()?,
+        months: match read_u32(r).ok()? {
+            0 => None,
+            bits => Some((1..=12).filter(|m| bits & (1 << m) != 0).collect()),
+        },
+        weight: Some(f64::from_bits(read_u64(r).ok()?)).filter(|w| *w > 0.0),
+    })
+}
+
+fn write_field(w: &mut impl Write, field: Option<&String>) -> io::Result<u64> {
+    match field {
+        Some(s) => {
+            w.write_all(&(s.len() as u32).to_le_bytes())?;
+            w.write_all(s.as_bytes())?;
+            Ok(4 + s.len() as u64)
+        }
+        None => w.write_all(&NONE.to_le_bytes()).map(|_| 4),
+    }
+}
+
+// Write the cache of a pack as it is read. Fails on packs with errors.
+fn write_cache(path: &Path, pack: &Path, header: Header) -> io::Result<Header> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // written next to the cache and renamed, so readers never see half a file
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    let result = (|| {
+        let mut out = io::BufWriter::new(File::create(&tmp)?);
+        write_header(&mut out, &header)?;
+        let mut entries = Vec::new();
+        let mut position = HEADER_LEN;
+        let mut total = 0.0;
+        let mut weighted = false;
+        let mut failed = None;
+        let diagnostics = stream_file(pack, &mut |q, _| {
+            total += q.weight.unwrap_or(1.0);
+            weighted |= q.weight.is_some();
+            entries.push((position, total));
+            let fields = [
+                Some(&q.japanese),
+                q.translation.as_ref(),
+                q.romaji.as_ref(),
+                q.source.as_ref(),
+                q.id.as_ref(),
+                q.season.as_ref(),
+                q.time.as_ref(),
+            ];
+            for field in fields {
+                match write_field(&mut out, field) {
+                    Ok(len) => position += len,
+                    Err(e) => failed = Some(e),
+                }
+            }
+            let months = q
+                .months
+                .iter()
+                .flatten()
+                .fold(0u32, |bits, m| bits | 1 << m);
+            let result = out
+                .write_all(&months.to_le_bytes())
+                .and_then(|_| out.write_all(&q.weight.unwrap_or(0.0).to_le_bytes()));
+            match result {
+                Ok(()) => position += 12,
+                Err(e) => failed = Some(e),
+            }
+            failed.is_none()
+        })
+        .map_err(io::Error::other)?;
+        if let Some(e) = failed {
+            return Err(e);
+        }
+        if diagnostics.iter().any(|d| d.level == Level::Error) {
+            return Err(io::Error::other("pack has errors"));
+        }
+
+        for (offset, weight) in &entries {
+            out.write_all(&offset.to_le_bytes())?;
+            out.write_all(&weight.to_le_bytes())?;
+        }
+        let header = Header {
+            count: entries.len() as u32,
+            offsets: position,
+            weight: if weighted { total } else { 0.0 },
+            ..header
+        };
+        out.seek(SeekFrom::Start(0))?;
+        write_header(&mut out, &header)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(header)
+    })();
+    match result {
+        Ok(header) => fs::rename(&tmp, path).map(|_| header),
+        Err(e) => {
+            fs::remove_file(&tmp).ok();
+            Err(e)
+        }
+    }
+}
+
+// The cached quotes of a pack file, rebuilding the cache if the file changed.
+// Returns None for packs that can't be cached: command packs, packs with errors (so
+// their errors keep being reported) and unreadable files.
+pub fn open(pack: &Path) -> Option<PackIndex> {
+    open_at(pack, &cache_file(pack))
+}
+
+// A cache file with its header, if it is whole.
+fn read_cache(path: &Path) -> Option<(File, Header)> {
+    let mut file = File::open(path).ok()?;
+    let header = read_header(&mut file).ok()?;
+    let len = file.metadata().ok()?.len();
+    let expected = header.offsets + ENTRY_LEN * header.count as u64;
+    (header.offsets >= HEADER_LEN && len == expected).then_some((file, header))
+}
+
+fn open_at(pack: &Path, path: &Path) -> Option<PackIndex> {
+    let meta = fs::metadata(pack).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let mtime = (mtime.as_secs(), mtime.subsec_nanos());
+    let size = meta.len();
+
+    let cached = read_cache(path);
+    if let Some((file, header)) = &cached
+        && header.mtime == mtime
+        && header.size == size
+    {
+        return Some(PackIndex::new(file.try_clone().ok()?, header));
+    }
+
+    let hash = hash_file(pack).ok()?;
+    if let Some((file, header)) = cached
+        && header.size == size
+        && header.hash == hash
+    {
+        // only touched, keep the quotes and record the new mtime
+        let header = Header { mtime, ..header };
+        if let Ok(mut f) = fs::OpenOptions::new().write(true).open(path) {
+            write_header(&mut f, &header).ok();
+        }
+        return Some(PackIndex::new(file, &header));
+    }
+
+    if is_command_pack(pack) {
+        return None;
+    }
+    let header = Header {
+        mtime,
+        size,
+        hash,
+        count: 0,
+        offsets: HEADER_LEN,
+        weight: 0.0,
+    };
+    // a cache that can't be written only costs speed
+    let header = write_cache(path, pack, header).ok()?;
+    File::open(path)
+        .ok()
+        .map(|file| PackIndex::new(file, &header))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory for a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kotofetch-{name}-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    const PACK: &str = r#"
+[[quote]]
+japanese = "猫に小判"
+translation = "Pearls before swine"
+weight = 2
+
+[[quote]]
+japanese = "花より団子"
+romaji = "hana yori dango"
+id = "dango"
+season = "spring"
+months = [3, 4]
+
+[[quote]]
+japanese = "一期一会"
+"#;
+
+    fn assert_pack(index: &PackIndex) {
+        assert_eq!(index.len(), 3);
+        // weights 2, 1 and 1
+        assert_eq!(index.weight(), Some(4.0));
+        assert_eq!(index.pick(1.9), Some(0));
+        assert_eq!(index.pick(2.5), Some(1));
+        assert_eq!(index.pick(3.5), Some(2));
+
+        let first = index.get(0).unwrap();
+        assert_eq!(first.japanese, "猫に小判");
+        assert_eq!(first.translation.as_deref(), Some("Pearls before swine"));
+        assert_eq!(first.romaji, None);
+        assert_eq!(first.weight, Some(2.0));
+        let second = index.get(1).unwrap();
+        assert_eq!(second.id.as_deref(), Some("dango"));
+        assert_eq!(second.season.as_deref(), Some("spring"));
+        assert_eq!(second.months, Some(vec![3, 4]));
+        assert_eq!(second.weight, None);
+        assert!(index.get(3).is_none());
+
+        let mut scanned = Vec::new();
+        index.scan(&mut |i, quote| scanned.push((i, quote.japanese.clone())));
+        assert_eq!(scanned[2], (2, "一期一会".to_string()));
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = test_dir("cache-round-trip");
+        let pack = dir.join("pack.toml");
+        fs::write(&pack, PACK).unwrap();
+        let cache = dir.join("cache.bin");
+
+        assert_pack(&open_at(&pack, &cache).unwrap());
+        // read back from the file written
+        assert!(read_cache(&cache).is_some());
+        assert_pack(&open_at(&pack, &cache).unwrap());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn broken_cache_is_rebuilt() {
+        let dir = test_dir("cache-broken");
+        let pack = dir.join("pack.toml");
+        fs::write(&pack, PACK).unwrap();
+        let cache = dir.join("cache.bin");
+        open_at(&pack, &cache).unwrap();
+        let len = fs::metadata(&cache).unwrap().len();
+
+        // cut in the offsets
+        let file = fs::OpenOptions::new().write(true).open(&cache).unwrap();
+        file.set_len(len - 5).unwrap();
+        drop(file);
+        assert!(read_cache(&cache).is_none());
+        assert_pack(&open_at(&pack, &cache).unwrap());
+        assert_eq!(fs::metadata(&cache).unwrap().len(), len);
+
+        // not a cache at all
+        fs::write(&cache, b"garbage").unwrap();
+        assert_pack(&open_at(&pack, &cache).unwrap());
+        assert_eq!(fs::metadata(&cache).unwrap().len(), len);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,363 @@
+use crate::config::RuntimeConfig;
+use crate::diagnostic::line_col;
+use crate::diagnostic::{Diagnostic, Level};
+use crate::display::simulate_font_size;
+use crate::formats::{FORMATS, detect_format, format_by_name, parse_pack};
+use crate::quotes::{PackSource, all_packs, resolve_pack};
+use crate::romaji::romaji_matches_kana;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use toml_edit::{Document, Item};
+use unicode_width::UnicodeWidthStr;
+
+fn unbalanced_brackets(s: &str) -> bool {
+    let mut stack = Vec::new();
+    for c in s.chars() {
+        match c {
+            '「' | '『' => stack.push(c),
+            '」' if stack.pop() != Some('「') => return true,
+            '』' if stack.pop() != Some('『') => return true,
+            _ => {}
+        }
+    }
+    !stack.is_empty()
+}
+
+struct Checker<'a> {
+    runtime: &'a RuntimeConfig,
+    diagnostics: Vec<Diagnostic>,
+    // whitespace-free japanese text -> where it was first seen
+    seen: HashMap<String, String>,
+    // command packs seen, whose output isn't checked
+    commands: usize,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, file: &str, location: Option<(usize, usize)>, level: Level, msg: String) {
+        self.diagnostics.push(Diagnostic {
+            file: file.to_string(),
+            location,
+            level,
+            message: msg,
+        });
+    }
+
+    // Check the keys of a command pack without running it. False if the file isn't one.
+    fn check_command_pack(&mut self, file: &str, src: &str) -> bool {
+        if detect_format(Path::new(file), src).name != "TOML" {
+            return false;
+        }
+        let Ok(doc) = Document::parse(src) else {
+            return false;
+        };
+        let Some(command) = doc.get("command") else {
+            return false;
+        };
+        self.commands += 1;
+        let location = |item: &Item| item.span().map(|s| line_col(src, s.start));
+
+        if command.as_str().is_none() {
+            let msg = "'command' must be a string".to_string();
+            self.report(file, location(command), Level::Error, msg);
+        }
+        if let Some(format) = doc.get("format") {
+            if let Some(name) = format.as_str() {
+                if format_by_name(name).is_none() {
+                    let names: Vec<&str> = FORMATS.iter().map(|f| f.name).collect();
+                    let msg = format!(
+                        "unknown format '{name}', expected one of {}",
+                        names.join(", ")
+                    );
+                    self.report(file, location(format), Level::Error, msg);
+                }
+            } else {
+                let msg = "'format' must be a string".to_string();
+                self.report(file, location(format), Level::Error, msg);
+            }
+        }
+        for key in ["quote", "japanese"] {
+            if let Some(item) = doc.get(key) {
+                let msg = format!("'{key}' next to 'command', a command pack holds no quotes");
+                self.report(file, location(item), Level::Error, msg);
+            }
+        }
+        true
+    }
+
+    fn check_pack(&mut self, file: &str, src: &str) {
+        // command packs aren't run, only their keys are checked
+        if self.check_command_pack(file, src) {
+            return;
+        }
+        let loaded = parse_pack(detect_format(Path::new(file), src), file, src);
+        self.diagnostics.extend(loaded.diagnostics);
+
+        // explicit id -> where it was first used, IDs only need to be unique in their pack
+        let mut ids: HashMap<&str, String> = HashMap::new();
+        for (quote, location) in &loaded.quotes {
+            let here = match location {
+                Some((line, col)) => format!("{file}:{line}:{col}"),
+                None => file.to_string(),
+            };
+            if let Some(id) = &quote.id {
+                if let Some(first) = ids.get(id.as_str()) {
+                    let msg = format!("duplicate id '{id}', first used at {first}");
+                    self.report(file, *location, Level::Error, msg);
+                } else {
+                    ids.insert(id, here.clone());
+                }
+            }
+
+            let japanese = quote.japanese.trim();
+
+            if japanese.is_empty() {
+                self.report(
+                    file,
+                    *location,
+                    Level::Warning,
+                    "empty 'japanese'".to_string(),
+                );
+                continue;
+            }
+
+            if unbalanced_brackets(japanese) {
+                self.report(
+                    file,
+                    *location,
+                    Level::Warning,
+                    "unbalanced 「」 or 『』".to_string(),
+                );
+            }
+
+            if let Some(romaji) = &quote.romaji
+                && romaji_matches_kana(japanese, romaji) == Some(false)
+            {
+                self.report(
+                    file,
+                    *location,
+                    Level::Warning,
+                    format!("romaji '{}' doesn't match the kana", romaji.trim()),
+                );
+            }
+
+            let width = self.runtime.width;
+            if width > 0 {
+                let displayed = simulate_font_size(japanese, &self.runtime.font_size);
+                if let Some(widest) = displayed.lines().map(UnicodeWidthStr::width).max()
+                    && widest > width
+                {
+                    self.report(
+                        file,
+                        *location,
+                        Level::Warning,
+                        format!("line is {widest} columns wide, configured width is {width}"),
+                    );
+                }
+            }
+
+            let key: String = japanese.chars().filter(|c| !c.is_whitespace()).collect();
+            if let Some(first) = self.seen.get(&key) {
+                let msg = format!("duplicate quote, first seen at {first}");
+                self.report(file, *location, Level::Warning, msg);
+            } else {
+                self.seen.insert(key, here);
+            }
+        }
+    }
+}
+
+// Every pack kotofetch can see, in the version that wins the search path.
+fn default_packs(search: &[PathBuf]) -> Vec<(String, Result<String, String>)> {
+    all_packs(search)
+        .into_values()
+        .filter_map(|sources| sources.into_iter().next())
+        .filter_map(|source| match source {
+            PackSource::File(path) => Some((
+                path.display().to_string(),
+                fs::read_to_string(&path).map_err(|e| e.to_string()),
+            )),
+            PackSource::Builtin(name, content) => {
+                Some((format!("<built-in>/{name}"), Ok(content.to_string())))
+            }
+            PackSource::Stdin => None,
+        })
+        .collect()
+}
+
+pub fn run(runtime: &RuntimeConfig, files: &[PathBuf], deny_warnings: bool) -> Result<(), String> {
+    let mut checker = Checker {
+        runtime,
+        diagnostics: Vec::new(),
+        seen: HashMap::new(),
+        commands: 0,
+    };
+
+    let search = runtime.quote_search_path();
+    let packs = if files.is_empty() {
+        // configured modes that resolve nowhere
+        for mode in &runtime.modes {
+            if resolve_pack(mode, &search).is_none() {
+                checker.diagnostics.push(Diagnostic {
+                    file: mode.display().to_string(),
+                    location: None,
+                    level: Level::Error,
+                    message: "mode file not found in quotes directories or built-in".to_string(),
+                });
+            }
+        }
+        default_packs(&search)
+    } else {
+        files
+            .iter()
+            .map(|p| {
+                if p == Path::new("-") {
+                    let mut src = String::new();
+                    let read = io::stdin().read_to_string(&mut src);
+                    return (
+                        "<stdin>".to_string(),
+                        read.map(|_| src).map_err(|e| e.to_string()),
+                    );
+                }
+                (
+                    p.display().to_string(),
+                    fs::read_to_string(p).map_err(|e| e.to_string()),
+                )
+            })
+            .collect()
+    };
+
+    for (file, content) in &packs {
+        match content {
+            Ok(src) => checker.check_pack(file, src),
+            Err(e) => checker.diagnostics.push(Diagnostic {
+                file: file.clone(),
+                location: None,
+                level: Level::Error,
+                message: format!("failed to read: {e}"),
+            }),
+        }
+    }
+
+    for d in &checker.diagnostics {
+        d.print();
+    }
+    let errors = checker
+        .diagnostics
+        .iter()
+        .filter(|d| d.level == Level::Error)
+        .count();
+    let warnings = checker.diagnostics.len() - errors;
+    eprintln!(
+        "{} packs checked: {} errors, {} warnings",
+        packs.len(),
+        errors,
+        warnings
+    );
+    if checker.commands > 0 {
+        eprintln!(
+            "{} command packs were not run, their output was not checked",
+            checker.commands
+        );
+    }
+
+    if errors > 0 || (deny_warnings && warnings > 0) {
+        Err("pack check failed".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker(runtime: &RuntimeConfig) -> Checker<'_> {
+        Checker {
+            runtime,
+            diagnostics: Vec::new(),
+            seen: HashMap::new(),
+            commands: 0,
+        }
+    }
+
+    // A pack written to a fresh file, for `run`.
+    fn pack_file(name: &str, content: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kotofetch-check-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    const ERROR_PACK: &str = "[[quote]]\njapanese = \"猫\"\nweight = -1\n";
+    const WARNING_PACK: &str = "[[quote]]\njapanese = \"「猫\"\n";
+
+    #[test]
+    fn error_is_located() {
+        let runtime = RuntimeConfig::default();
+        let mut checker = checker(&runtime);
+        checker.check_pack("bad.toml", ERROR_PACK);
+        let [d] = checker.diagnostics.as_slice() else {
+            panic!("{:?}", checker.diagnostics);
+        };
+        assert_eq!(d.level, Level::Error);
+        assert_eq!(d.location, Some((3, 1)));
+        assert!(d.message.contains("'weight' must be a positive number"));
+    }
+
+    #[test]
+    fn warnings_are_located() {
+        let runtime = RuntimeConfig::default();
+        let mut checker = checker(&runtime);
+        let src =
+            "[[quote]]\njapanese = \"「猫\"\n\n[[quote]]\njapanese = \"ねこ\"\nromaji = \"inu\"\n";
+        checker.check_pack("warn.toml", src);
+        let found: Vec<_> = checker
+            .diagnostics
+            .iter()
+            .map(|d| (d.level, d.location, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Level::Warning, Some((2, 1)), "unbalanced 「」 or 『』"),
+                (
+                    Level::Warning,
+                    Some((5, 1)),
+                    "romaji 'inu' doesn't match the kana"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn command_packs_are_checked_without_running() {
+        let runtime = RuntimeConfig::default();
+        let mut checker = checker(&runtime);
+        let src = "command = \"touch /tmp/kotofetch-ran\"\nformat = \"xml\"\n";
+        checker.check_pack("cmd.toml", src);
+        assert_eq!(checker.commands, 1);
+        assert_eq!(checker.diagnostics.len(), 1);
+        assert!(
+            checker.diagnostics[0]
+                .message
+                .starts_with("unknown format 'xml'")
+        );
+    }
+
+    #[test]
+    fn exit_status() {
+        let runtime = RuntimeConfig::default();
+        let good = pack_file("good.toml", "[[quote]]\njapanese = \"猫\"\n");
+        let bad = pack_file("bad.toml", ERROR_PACK);
+        let warn = pack_file("warn.toml", WARNING_PACK);
+
+        assert!(run(&runtime, std::slice::from_ref(&good), false).is_ok());
+        assert!(run(&runtime, std::slice::from_ref(&good), true).is_ok());
+        assert!(run(&runtime, std::slice::from_ref(&bad), false).is_err());
+        assert!(run(&runtime, std::slice::from_ref(&warn), false).is_ok());
+        assert!(run(&runtime, std::slice::from_ref(&warn), true).is_err());
+    }
+}
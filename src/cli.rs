@@ -1,13 +1,25 @@
-use clap::{Parser, ValueEnum};
+use crate::context::Now;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     // Path to config file (TOML). Defaults to ~/.config/kotofetch/config.toml
     #[arg(short, long)]
     pub config: Option<PathBuf>,
 
+    // Config profile to use, see [profile.<name>] in the config
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    // Fail on any config problem instead of warning and using defaults
+    #[arg(long)]
+    pub strict: bool,
+
     // Horizontal padding
     #[arg(long)]
     pub horizontal_padding: Option<usize>,
@@ -25,11 +37,11 @@ pub struct Cli {
     pub translation: Option<TranslationMode>,
 
     // Translation color (hex like #888888 or named)
-    #[arg(long)]
+    #[arg(long, value_parser = color_arg)]
     pub translation_color: Option<String>,
 
     // Quote color (hex like #888888 or named)
-    #[arg(long)]
+    #[arg(long, value_parser = color_arg)]
     pub quote_color: Option<String>,
 
     // Make Japanese text bold
@@ -45,7 +57,7 @@ pub struct Cli {
     pub rounded_border: Option<bool>,
 
     // Border color (hex like #888888 or named)
-    #[arg(long)]
+    #[arg(long, value_parser = color_arg)]
     pub border_color: Option<String>,
 
     // Show quote source
@@ -56,17 +68,96 @@ pub struct Cli {
     #[arg(long, value_delimiter = ',', num_args = 1.., required = false)]
     pub modes: Option<Vec<PathBuf>>,
 
+    // How to pick a quote: quote (any quote, by weight) or pack (a pack, then a quote)
+    #[arg(long, value_enum)]
+    pub selection: Option<Selection>,
+
+    // Prefer quotes that fit the season, the time of day and special days
+    #[arg(long)]
+    pub contextual: Option<bool>,
+
+    // Pretend it is this local date for contextual selection, like 2026-07-07T21:00
+    #[arg(long, value_parser = date_arg)]
+    pub date: Option<Now>,
+
+    // Read the quotes from stdin (TOML, JSON or japanese|translation|romaji|source lines)
+    #[arg(long, conflicts_with = "modes")]
+    pub stdin: bool,
+
+    // Parse the quote files instead of reading the pack cache
+    #[arg(long)]
+    pub no_cache: bool,
+
+    // Extra directory to search for quote files first (can be repeated)
+    #[arg(long = "quotes-dir")]
+    pub quotes_dir: Vec<PathBuf>,
+
     // Choose a specific quote by index (0-based) for reproducible output
     #[arg(long)]
     pub index: Option<usize>,
 
+    // Choose a quote by its ID, like anime:3f9a (see `kotofetch list`)
+    #[arg(long, conflicts_with = "index")]
+    pub id: Option<String>,
+
     // Seed for random selection (0 = random by time)
     #[arg(long)]
     pub seed: Option<u64>,
 
+    // Print the seed used on stderr, to get the same quote again with --seed
+    #[arg(long)]
+    pub print_seed: bool,
+
+    // Print how the quote was chosen on stderr, with its replay token
+    #[arg(long)]
+    pub explain: bool,
+
+    // Show the quote of a replay token from --explain, like anime:12@1234
+    #[arg(long, conflicts_with_all = ["index", "id", "seed"])]
+    pub replay: Option<String>,
+
     // Center text
     #[arg(long)]
     pub centered: Option<bool>,
+
+    // Show the quote on one line, like 逃げちゃダメだ — You mustn't run away.
+    #[arg(long)]
+    pub oneline: bool,
+
+    // Pick a quote that fits in this many columns, cutting it if none does
+    #[arg(long)]
+    pub max_width: Option<usize>,
+
+    // Pick a quote that fits in this many lines, cutting it if none does
+    #[arg(long)]
+    pub max_lines: Option<usize>,
+
+    // Print the quote for a status bar, a shell prompt or as a picture (svg, html)
+    #[arg(long, value_enum, default_value_t = OutputFormat::Terminal)]
+    pub format: OutputFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Terminal,
+    Tmux,
+    Waybar,
+    I3blocks,
+    Polybar,
+    ZshPrompt,
+    BashPrompt,
+    Svg,
+    Html,
+}
+
+impl OutputFormat {
+    // Bars and prompts show the quote on one line.
+    pub fn one_line(self) -> bool {
+        !matches!(
+            self,
+            OutputFormat::Terminal | OutputFormat::Svg | OutputFormat::Html
+        )
+    }
 }
 
 #[derive(ValueEnum, Clone, Debug, PartialEq, Eq)]
@@ -75,3 +166,312 @@ pub enum TranslationMode {
     English,
     Romaji,
 }
+
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum Selection {
+    Quote,
+    Pack,
+}
+
+// Reject colors that can't be parsed right away.
+fn color_arg(s: &str) -> Result<String, String> {
+    if crate::display::parse_color(s).is_some() {
+        Ok(s.to_string())
+    } else {
+        Err(format!(
+            "unknown color '{s}', expected a named color like \"red\" or a hex color like \"#ffcc00\""
+        ))
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    // Quiz yourself on the meaning or the reading of quotes
+    Quiz(QuizArgs),
+
+    // Add a quote to a pack (prompts for the fields if --japanese is missing)
+    Add(AddArgs),
+
+    // List quotes with their IDs
+    List(ListArgs),
+
+    // Show a single quote
+    Show {
+        // Quote ID, like anime:3f9a
+        id: String,
+    },
+
+    // Remove a quote from its pack
+    Remove {
+        // Quote ID, like anime:3f9a
+        id: String,
+    },
+
+    // Open a quote in $EDITOR
+    Edit {
+        // Quote ID, like anime:3f9a
+        id: String,
+    },
+
+    // Add a quote to the favorites (the @favorites mode), or list them
+    Fav(MarkArgs),
+
+    // Never show a quote again, or list the hidden quotes
+    Hide(MarkArgs),
+
+    // List every known pack and the file it resolves to
+    Packs,
+
+    // Write quotes to a file for Anki, a document or a spreadsheet
+    Export(ExportArgs),
+
+    // Write a quote as a login banner, for /etc/motd or update-motd.d
+    Motd(MotdArgs),
+
+    // Draw a quote as a PNG picture, for wallpapers and lock screens
+    Render(RenderArgs),
+
+    // Turn flashcards from another program into a quote pack
+    Import {
+        #[command(subcommand)]
+        from: ImportFrom,
+    },
+
+    // Manage the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    // Validate quote packs (all known packs if no file is given)
+    Check {
+        files: Vec<PathBuf>,
+
+        // Exit with an error on warnings too
+        #[arg(long)]
+        deny_warnings: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    // Write a commented config.toml with the default values
+    Init {
+        // Overwrite an existing config
+        #[arg(long)]
+        force: bool,
+    },
+
+    // Print the effective config and where each value comes from
+    Show,
+
+    // Print the config and quotes locations
+    Path,
+
+    // Open the config in $EDITOR
+    Edit,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    #[arg(long, value_enum)]
+    pub format: ExportFormat,
+
+    // Packs to export (defaults to the display modes)
+    #[arg(long, value_delimiter = ',', num_args = 1.., required = false)]
+    pub pack: Option<Vec<PathBuf>>,
+
+    // File to write instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct MotdArgs {
+    // File to write, like /etc/motd (stdout if not given)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    // Width of the screens the banner is shown on, in columns
+    #[arg(long, default_value_t = 80)]
+    pub columns: usize,
+
+    // Only use ASCII: romaji instead of Japanese and a plain border
+    #[arg(long)]
+    pub ascii: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct RenderArgs {
+    // PNG file to write
+    #[arg(long)]
+    pub png: PathBuf,
+
+    // Japanese font (.ttf, .otf or .ttc). None is bundled: without it, Noto CJK, Hiragino or Yu Gothic must be installed
+    #[arg(long)]
+    pub font: Option<PathBuf>,
+
+    // Picture size in pixels, at most 8192x8192
+    #[arg(long, value_parser = size_arg, default_value = "1920x1080")]
+    pub size: (u32, u32),
+
+    // Background color (hex like #1e1e1e or named)
+    #[arg(long, value_parser = color_arg, default_value = "#1e1e1e")]
+    pub background: String,
+
+    // Color the background fades to at the bottom
+    #[arg(long, value_parser = color_arg)]
+    pub gradient: Option<String>,
+
+    // Where the card goes: centered, or left with the text aligned to the left
+    #[arg(long, value_enum, default_value_t = CardLayout::Centered)]
+    pub layout: CardLayout,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CardLayout {
+    Centered,
+    Left,
+}
+
+fn size_arg(s: &str) -> Result<(u32, u32), String> {
+    let invalid =
+        || format!("invalid size '{s}', expected WIDTHxHEIGHT like 1920x1080, at most 8192x8192");
+    let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+    let size = |n: &str| n.parse::<u32>().ok().filter(|n| (1..=8192).contains(n));
+    match (size(width), size(height)) {
+        (Some(width), Some(height)) => Ok((width, height)),
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    // Tab separated notes for Anki's "Import File"
+    AnkiTsv,
+    Markdown,
+    // Standalone page, ready to print
+    Html,
+    Csv,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ImportFrom {
+    // An Anki deck (.apkg, .colpkg, .anki2) or a plain text export of its notes
+    Anki(AnkiArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct AnkiArgs {
+    pub file: PathBuf,
+
+    // Pack to write (defaults to the deck file name)
+    #[arg(long)]
+    pub pack: Option<PathBuf>,
+
+    // Field holding the Japanese text, by name or 1-based number
+    #[arg(long, default_value = "1")]
+    pub front_field: String,
+
+    // Field holding the translation
+    #[arg(long, default_value = "2")]
+    pub back_field: String,
+
+    // Field holding the reading, in romaji or kana
+    #[arg(long)]
+    pub reading_field: Option<String>,
+
+    // Remove HTML tags and entities from the fields
+    #[arg(long)]
+    pub strip_html: bool,
+
+    // Remove furigana like 漢字[かんじ] from the Japanese text
+    #[arg(long)]
+    pub strip_furigana: bool,
+
+    // Replace the pack if it already exists
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AddArgs {
+    // Pack to add the quote to
+    #[arg(long, default_value = "mine")]
+    pub pack: PathBuf,
+
+    #[arg(long)]
+    pub japanese: Option<String>,
+
+    #[arg(long)]
+    pub translation: Option<String>,
+
+    #[arg(long)]
+    pub romaji: Option<String>,
+
+    #[arg(long)]
+    pub source: Option<String>,
+
+    // How likely the quote is to be picked, relative to the others of the pack (1)
+    #[arg(long, value_parser = weight_arg)]
+    pub weight: Option<f64>,
+}
+
+fn date_arg(s: &str) -> Result<Now, String> {
+    Now::parse(s)
+}
+
+fn weight_arg(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(w) if w.is_finite() && w > 0.0 => Ok(w),
+        _ => Err(format!("'{s}' is not a positive number")),
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct MarkArgs {
+    // Quote ID, like anime:3f9a
+    pub id: Option<String>,
+
+    // The quote shown last
+    #[arg(long, conflicts_with = "id")]
+    pub last: bool,
+
+    // Take the quote off the list instead
+    #[arg(long)]
+    pub remove: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    // Packs to list (defaults to the display modes)
+    #[arg(long, value_delimiter = ',', num_args = 1.., required = false)]
+    pub pack: Option<Vec<PathBuf>>,
+}
+
+#[derive(Args, Debug)]
+pub struct QuizArgs {
+    // What to ask for: meaning, reading or mixed
+    #[arg(long, value_enum)]
+    pub kind: Option<QuizKind>,
+
+    // Number of questions
+    #[arg(short = 'n', long)]
+    pub questions: Option<usize>,
+
+    // Number of choices for meaning questions
+    #[arg(long)]
+    pub choices: Option<usize>,
+
+    // Quote files to draw questions from (defaults to the display modes)
+    #[arg(long, value_delimiter = ',', num_args = 1.., required = false)]
+    pub packs: Option<Vec<PathBuf>>,
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum QuizKind {
+    Meaning,
+    Reading,
+    Mixed,
+}
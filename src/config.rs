@@ -1,11 +1,38 @@
+use crate::cache::cache_dir;
+use crate::diagnostic::{Diagnostic, Level, did_you_mean, line_col};
+use crate::display::{FONT_SIZES, parse_color};
+use crate::manage::open_in_editor;
+use crate::quotes::{search_path, user_quotes_dir};
+use crate::state::state_dir;
+use console::Style;
 use dirs::config_dir;
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml_edit::{Document, Item, TableLike, Value};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct FileConfig {
+    // where the config was read from
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    pub strict: Option<bool>,
+    // let command packs run, only read from the user config itself
+    pub allow_command_packs: Option<bool>,
+    // whether this is the user config, not a system, project or included one
+    #[serde(skip)]
+    pub user: bool,
+    // directories whose .kotofetch.toml is loaded, only read from the user config
+    pub trusted_projects: Option<Vec<String>>,
+    // other config files to load first, relative to this one
+    pub include: Option<Vec<String>>,
     pub display: Option<DisplayConfig>,
+    pub profile: Option<BTreeMap<String, ProfileConfig>>,
+    pub quiz: Option<QuizConfig>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -16,6 +43,34 @@ pub enum TranslationMode {
     Romaji,
 }
 
+impl TranslationMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TranslationMode::None => "none",
+            TranslationMode::English => "english",
+            TranslationMode::Romaji => "romaji",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuizKind {
+    Meaning,
+    Reading,
+    Mixed,
+}
+
+impl QuizKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuizKind::Meaning => "meaning",
+            QuizKind::Reading => "reading",
+            QuizKind::Mixed => "mixed",
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct DisplayConfig {
     pub horizontal_padding: Option<usize>,
@@ -30,11 +85,194 @@ pub struct DisplayConfig {
     pub rounded_border: Option<bool>,
     pub border_color: Option<String>,
     pub source: Option<bool>,
-    pub modes: Option<Vec<PathBuf>>,
+    pub modes: Option<Vec<ModeEntry>>,
+    pub selection: Option<Selection>,
+    pub contextual: Option<bool>,
+    pub quotes_dirs: Option<Vec<PathBuf>>,
     pub seed: Option<u64>,
     pub centered: Option<bool>,
 }
 
+// An entry of `modes`: a quote file, or a table giving it a weight.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(
+    untagged,
+    deny_unknown_fields,
+    expecting = "expected a quote file or a table like { name = \"haiku\", weight = 3 }"
+)]
+pub enum ModeEntry {
+    Name(PathBuf),
+    Weighted { name: PathBuf, weight: Option<f64> },
+}
+
+impl ModeEntry {
+    fn path(&self) -> &Path {
+        match self {
+            ModeEntry::Name(name) | ModeEntry::Weighted { name, .. } => name,
+        }
+    }
+
+    fn weight(&self) -> Option<f64> {
+        match self {
+            ModeEntry::Name(_) => None,
+            ModeEntry::Weighted { weight, .. } => *weight,
+        }
+    }
+}
+
+// How a quote is picked among the packs.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    // any quote, by its weight times its pack's weight
+    Quote,
+    // a pack by its weight, then one of its quotes
+    Pack,
+}
+
+impl Selection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Selection::Quote => "quote",
+            Selection::Pack => "pack",
+        }
+    }
+}
+
+// A [profile.<name>] table: display overrides, optionally on top of another profile.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProfileConfig {
+    pub inherits: Option<String>,
+    #[serde(flatten)]
+    pub display: DisplayConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct QuizConfig {
+    pub kind: Option<QuizKind>,
+    pub questions: Option<usize>,
+    pub choices: Option<usize>,
+    pub packs: Option<Vec<PathBuf>>,
+}
+
+// Take the values that are set in `$other`.
+macro_rules! overlay {
+    ($self:ident, $other:ident, $($field:ident),*) => {
+        $(
+            if $other.$field.is_some() {
+                $self.$field = $other.$field;
+            }
+        )*
+    };
+}
+
+impl DisplayConfig {
+    // Override the values that are set in `other`.
+    pub fn overlay(&mut self, other: DisplayConfig) {
+        overlay!(
+            self,
+            other,
+            horizontal_padding,
+            vertical_padding,
+            width,
+            show_translation,
+            translation_color,
+            quote_color,
+            font_size,
+            bold,
+            border,
+            rounded_border,
+            border_color,
+            source,
+            modes,
+            selection,
+            contextual,
+            quotes_dirs,
+            seed,
+            centered
+        );
+    }
+
+    // The display options given on the command line.
+    pub fn from_cli(cli: &crate::cli::Cli) -> Self {
+        Self {
+            horizontal_padding: cli.horizontal_padding,
+            vertical_padding: cli.vertical_padding,
+            width: cli.width,
+            // map CLI TranslationMode -> config::TranslationMode
+            show_translation: cli.translation.as_ref().map(|t| match t {
+                crate::cli::TranslationMode::None => TranslationMode::None,
+                crate::cli::TranslationMode::English => TranslationMode::English,
+                crate::cli::TranslationMode::Romaji => TranslationMode::Romaji,
+            }),
+            translation_color: cli.translation_color.clone(),
+            quote_color: cli.quote_color.clone(),
+            font_size: None,
+            bold: cli.bold,
+            border: cli.border,
+            rounded_border: cli.rounded_border,
+            border_color: cli.border_color.clone(),
+            source: cli.source,
+            // --stdin is the same as --modes -
+            modes: if cli.stdin {
+                Some(vec![ModeEntry::Name(PathBuf::from("-"))])
+            } else {
+                cli.modes
+                    .as_ref()
+                    .map(|modes| modes.iter().cloned().map(ModeEntry::Name).collect())
+            },
+            selection: cli.selection.as_ref().map(|s| match s {
+                crate::cli::Selection::Quote => Selection::Quote,
+                crate::cli::Selection::Pack => Selection::Pack,
+            }),
+            contextual: cli.contextual,
+            // --quotes-dir adds to the configured directories, see make_runtime_config
+            quotes_dirs: None,
+            seed: cli.seed,
+            centered: cli.centered,
+        }
+    }
+}
+
+impl QuizConfig {
+    // The quiz options given on the command line.
+    pub fn from_cli(q: &crate::cli::QuizArgs) -> Self {
+        Self {
+            // map CLI QuizKind -> config::QuizKind
+            kind: q.kind.as_ref().map(|k| match k {
+                crate::cli::QuizKind::Meaning => QuizKind::Meaning,
+                crate::cli::QuizKind::Reading => QuizKind::Reading,
+                crate::cli::QuizKind::Mixed => QuizKind::Mixed,
+            }),
+            questions: q.questions,
+            choices: q.choices,
+            packs: q.packs.clone(),
+        }
+    }
+}
+
+// Where a runtime value was set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+    Profile(String),
+    Env,
+    Cli,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File(path) => write!(f, "file {}", path.display()),
+            Origin::Profile(name) => write!(f, "profile {name}"),
+            Origin::Env => write!(f, "environment"),
+            Origin::Cli => write!(f, "command line"),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct RuntimeConfig {
     pub horizontal_padding: usize,
@@ -50,8 +288,19 @@ pub struct RuntimeConfig {
     pub border_color: String,
     pub source: bool,
     pub modes: Vec<PathBuf>,
+    pub mode_weights: HashMap<PathBuf, f64>, // modes without a weight count as 1
+    pub selection: Selection,
+    pub contextual: bool,
+    pub quotes_dirs: Vec<PathBuf>, // searched before the default quotes directories
+    pub command_dirs: Vec<PathBuf>, // where command packs may run, empty unless allowed
     pub seed: u64,
     pub centered: bool,
+    pub quiz_kind: QuizKind,
+    pub quiz_questions: usize,
+    pub quiz_choices: usize,
+    pub quiz_packs: Option<Vec<PathBuf>>, // None = same as modes
+    // "section.key" -> where it was set, missing keys are defaults
+    pub origins: HashMap<&'static str, Origin>,
 }
 
 impl Default for RuntimeConfig {
@@ -75,147 +324,1278 @@ impl Default for RuntimeConfig {
                 PathBuf::from("haiku.toml"),
                 PathBuf::from("anime.toml"),
             ],
+            mode_weights: HashMap::new(),
+            selection: Selection::Quote,
+            contextual: false,
+            quotes_dirs: Vec::new(),
+            command_dirs: Vec::new(),
             seed: 0, // 0 = random
             centered: true,
+            quiz_kind: QuizKind::Mixed,
+            quiz_questions: 10,
+            quiz_choices: 4,
+            quiz_packs: None,
+            origins: HashMap::new(),
         }
     }
 }
 
-pub fn load_user_config(path_override: Option<PathBuf>) -> Option<FileConfig> {
-    let path = if let Some(p) = path_override {
-        p
-    } else if let Some(mut d) = config_dir() {
-        d.push("kotofetch/config.toml");
-        d
+fn paths_value(paths: &[PathBuf]) -> Value {
+    Value::from_iter(paths.iter().map(|p| p.to_string_lossy().into_owned()))
+}
+
+// Whole weights are shown as integers, like they are usually written.
+pub fn weight_value(weight: f64) -> Value {
+    if weight.fract() == 0.0 && weight < i64::MAX as f64 {
+        Value::from(weight as i64)
     } else {
-        return None;
+        Value::from(weight)
+    }
+}
+
+// Set a runtime field from a layer value, remembering where it came from.
+macro_rules! apply {
+    ($r:ident, $origin:ident, $field:ident, $key:literal, $value:expr) => {
+        if let Some(v) = $value {
+            $r.$field = v;
+            $r.origins.insert($key, $origin.clone());
+        }
     };
+}
+
+impl RuntimeConfig {
+    pub fn apply_display(&mut self, d: DisplayConfig, origin: &Origin) {
+        apply!(
+            self,
+            origin,
+            horizontal_padding,
+            "display.horizontal_padding",
+            d.horizontal_padding
+        );
+        apply!(
+            self,
+            origin,
+            vertical_padding,
+            "display.vertical_padding",
+            d.vertical_padding
+        );
+        apply!(self, origin, width, "display.width", d.width);
+        apply!(
+            self,
+            origin,
+            show_translation,
+            "display.show_translation",
+            d.show_translation
+        );
+        apply!(
+            self,
+            origin,
+            translation_color,
+            "display.translation_color",
+            d.translation_color
+        );
+        apply!(
+            self,
+            origin,
+            quote_color,
+            "display.quote_color",
+            d.quote_color
+        );
+        apply!(self, origin, font_size, "display.font_size", d.font_size);
+        apply!(self, origin, bold, "display.bold", d.bold);
+        apply!(self, origin, border, "display.border", d.border);
+        apply!(
+            self,
+            origin,
+            rounded_border,
+            "display.rounded_border",
+            d.rounded_border
+        );
+        apply!(
+            self,
+            origin,
+            border_color,
+            "display.border_color",
+            d.border_color
+        );
+        apply!(self, origin, source, "display.source", d.source);
+        if let Some(entries) = d.modes {
+            self.mode_weights = entries
+                .iter()
+                .filter_map(|e| Some((e.path().to_path_buf(), e.weight()?)))
+                .collect();
+            let modes = entries.iter().map(|e| e.path().to_path_buf()).collect();
+            apply!(self, origin, modes, "display.modes", Some(modes));
+        }
+        apply!(self, origin, selection, "display.selection", d.selection);
+        apply!(self, origin, contextual, "display.contextual", d.contextual);
+        apply!(
+            self,
+            origin,
+            quotes_dirs,
+            "display.quotes_dirs",
+            d.quotes_dirs
+        );
+        apply!(self, origin, seed, "display.seed", d.seed);
+        apply!(self, origin, centered, "display.centered", d.centered);
+    }
+
+    pub fn apply_quiz(&mut self, q: QuizConfig, origin: &Origin) {
+        apply!(self, origin, quiz_kind, "quiz.kind", q.kind);
+        apply!(self, origin, quiz_questions, "quiz.questions", q.questions);
+        apply!(self, origin, quiz_choices, "quiz.choices", q.choices);
+        apply!(self, origin, quiz_packs, "quiz.packs", q.packs.map(Some));
+    }
 
-    if path.exists() {
-        match fs::read_to_string(&path) {
-            Ok(s) => match toml::from_str::<FileConfig>(&s) {
-                Ok(parsed) => Some(parsed),
-                Err(e) => {
-                    eprintln!("Failed to parse config.toml: {}", e);
-                    None
+    // Directories searched for quote files, most important first.
+    pub fn quote_search_path(&self) -> Vec<PathBuf> {
+        let extra: Vec<PathBuf> = self
+            .quotes_dirs
+            .iter()
+            .map(|d| expand_tilde(&d.to_string_lossy()))
+            .collect();
+        search_path(&extra)
+    }
+
+    // The weight of each mode, in order.
+    pub fn pack_weights(&self) -> Vec<f64> {
+        self.modes
+            .iter()
+            .map(|m| self.mode_weights.get(m).copied().unwrap_or(1.0))
+            .collect()
+    }
+
+    // `modes` as written in the config, with a table for weighted modes.
+    fn modes_value(&self) -> Value {
+        Value::from_iter(self.modes.iter().map(|m| {
+            let name = m.to_string_lossy().into_owned();
+            match self.mode_weights.get(m) {
+                Some(weight) => {
+                    let mut table = toml_edit::InlineTable::new();
+                    table.insert("name", Value::from(name));
+                    table.insert("weight", weight_value(*weight));
+                    Value::InlineTable(table)
                 }
-            },
-            Err(e) => {
-                eprintln!("Failed to read config: {}", e);
-                None
+                None => Value::from(name),
             }
-        }
-    } else {
-        None
+        }))
+    }
+
+    pub fn origin(&self, key: &str) -> Origin {
+        self.origins.get(key).cloned().unwrap_or(Origin::Default)
+    }
+
+    // Every setting as (section, key, value), None for unset optional values.
+    pub fn entries(&self) -> Vec<(&'static str, &'static str, Option<Value>)> {
+        vec![
+            (
+                "display",
+                "horizontal_padding",
+                Some(Value::from(self.horizontal_padding as i64)),
+            ),
+            (
+                "display",
+                "vertical_padding",
+                Some(Value::from(self.vertical_padding as i64)),
+            ),
+            ("display", "width", Some(Value::from(self.width as i64))),
+            (
+                "display",
+                "show_translation",
+                Some(Value::from(self.show_translation.as_str())),
+            ),
+            (
+                "display",
+                "translation_color",
+                Some(Value::from(&self.translation_color)),
+            ),
+            (
+                "display",
+                "quote_color",
+                Some(Value::from(&self.quote_color)),
+            ),
+            ("display", "font_size", Some(Value::from(&self.font_size))),
+            ("display", "bold", Some(Value::from(self.bold))),
+            ("display", "border", Some(Value::from(self.border))),
+            (
+                "display",
+                "rounded_border",
+                Some(Value::from(self.rounded_border)),
+            ),
+            (
+                "display",
+                "border_color",
+                Some(Value::from(&self.border_color)),
+            ),
+            ("display", "source", Some(Value::from(self.source))),
+            ("display", "modes", Some(self.modes_value())),
+            (
+                "display",
+                "selection",
+                Some(Value::from(self.selection.as_str())),
+            ),
+            ("display", "contextual", Some(Value::from(self.contextual))),
+            (
+                "display",
+                "quotes_dirs",
+                Some(paths_value(&self.quotes_dirs)),
+            ),
+            ("display", "seed", Some(Value::from(self.seed as i64))),
+            ("display", "centered", Some(Value::from(self.centered))),
+            ("quiz", "kind", Some(Value::from(self.quiz_kind.as_str()))),
+            (
+                "quiz",
+                "questions",
+                Some(Value::from(self.quiz_questions as i64)),
+            ),
+            (
+                "quiz",
+                "choices",
+                Some(Value::from(self.quiz_choices as i64)),
+            ),
+            ("quiz", "packs", self.quiz_packs.as_deref().map(paths_value)),
+        ]
     }
 }
 
-pub fn make_runtime_config(user: Option<FileConfig>, cli: &crate::cli::Cli) -> RuntimeConfig {
-    let mut r = RuntimeConfig::default();
+// Keys accepted in each section of config.toml, used to spot typos.
+const ROOT_KEYS: &[&str] = &[
+    "strict",
+    "allow_command_packs",
+    "trusted_projects",
+    "include",
+    "display",
+    "profile",
+    "quiz",
+];
+const DISPLAY_KEYS: &[&str] = &[
+    "horizontal_padding",
+    "vertical_padding",
+    "width",
+    "show_translation",
+    "translation_color",
+    "quote_color",
+    "font_size",
+    "bold",
+    "border",
+    "rounded_border",
+    "border_color",
+    "source",
+    "modes",
+    "selection",
+    "contextual",
+    "quotes_dirs",
+    "seed",
+    "centered",
+];
+const QUIZ_KEYS: &[&str] = &["kind", "questions", "choices", "packs"];
 
-    // apply user file config
-    if let Some(uf) = user {
-        if let Some(d) = uf.display {
-            if let Some(p) = d.horizontal_padding {
-                r.horizontal_padding = p;
-            }
-            if let Some(p) = d.vertical_padding {
-                r.vertical_padding = p;
-            }
-            if let Some(w) = d.width {
-                r.width = w;
-            }
-            if let Some(st) = d.show_translation {
-                r.show_translation = st;
-            }
-            if let Some(tc) = d.translation_color {
-                r.translation_color = tc;
-            }
-            if let Some(qc) = d.quote_color {
-                r.quote_color = qc;
-            }
-            if let Some(fs) = d.font_size {
-                r.font_size = fs;
-            }
-            if let Some(b) = d.bold {
-                r.bold = b;
+struct ConfigChecker<'a> {
+    file: &'a str,
+    src: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ConfigChecker<'_> {
+    fn report(&mut self, span: Option<Range<usize>>, level: Level, message: String) {
+        self.diagnostics.push(Diagnostic {
+            file: self.file.to_string(),
+            location: span.map(|s| line_col(self.src, s.start)),
+            level,
+            message,
+        });
+    }
+
+    fn check_keys(&mut self, table: &dyn TableLike, section: &str, known: &[&str]) {
+        for (key, _) in table.iter() {
+            if known.contains(&key) {
+                continue;
             }
-            if let Some(b) = d.border {
-                r.border = b;
+            let span = table.key(key).and_then(|k| k.span());
+            let name = if section.is_empty() {
+                key.to_string()
+            } else {
+                format!("{section}.{key}")
+            };
+            let msg = match did_you_mean(key, known) {
+                Some(suggestion) => format!("unknown key '{name}', did you mean '{suggestion}'?"),
+                None => format!("unknown key '{name}'"),
+            };
+            self.report(span, Level::Warning, msg);
+        }
+    }
+
+    // Check the keys and values of [display] or of a profile.
+    fn check_display(
+        &mut self,
+        table: &dyn TableLike,
+        section: &str,
+        d: &mut DisplayConfig,
+        known: &[&str],
+    ) {
+        self.check_keys(table, section, known);
+
+        if let Some(size) = &d.font_size
+            && !FONT_SIZES.contains(&size.as_str())
+        {
+            let span = table.get("font_size").and_then(Item::span);
+            let msg = format!(
+                "invalid font_size '{size}', expected one of {}",
+                FONT_SIZES.join(", ")
+            );
+            self.report(span, Level::Warning, msg);
+            d.font_size = None;
+        }
+        if let Some(modes) = &mut d.modes {
+            for entry in modes {
+                if let ModeEntry::Weighted { name, weight } = entry
+                    && let Some(w) = *weight
+                    && !(w.is_finite() && w > 0.0)
+                {
+                    let span = table.get("modes").and_then(Item::span);
+                    let msg = format!(
+                        "invalid weight {w} for mode '{}', expected a positive number",
+                        name.display()
+                    );
+                    self.report(span, Level::Warning, msg);
+                    *weight = None;
+                }
             }
-            if let Some(b) = d.rounded_border {
-                r.rounded_border = b;
+        }
+        self.check_color(table, "quote_color", &mut d.quote_color);
+        self.check_color(table, "translation_color", &mut d.translation_color);
+        self.check_color(table, "border_color", &mut d.border_color);
+    }
+
+    // Warn about a color that can't be parsed and drop it so the default applies.
+    fn check_color(&mut self, display: &dyn TableLike, key: &str, value: &mut Option<String>) {
+        if let Some(color) = value
+            && parse_color(color).is_none()
+        {
+            let span = display.get(key).and_then(Item::span);
+            let msg = format!(
+                "invalid {key} '{color}', expected a named color like \"red\" or a hex color like \"#ffcc00\""
+            );
+            self.report(span, Level::Warning, msg);
+            *value = None;
+        }
+    }
+}
+
+// The path of keys to the deepest value of a document found at `offset`.
+fn key_at(table: &dyn TableLike, offset: usize) -> Option<Vec<String>> {
+    for (key, item) in table.iter() {
+        if let Some(inner) = item.as_table_like()
+            && let Some(mut path) = key_at(inner, offset)
+        {
+            path.insert(0, key.to_string());
+            return Some(path);
+        }
+        if item.span().is_some_and(|span| span.contains(&offset)) {
+            return Some(vec![key.to_string()]);
+        }
+    }
+    None
+}
+
+fn item_at<'a>(table: &'a dyn TableLike, path: &[String]) -> Option<&'a Item> {
+    let (last, parents) = path.split_last()?;
+    let mut table = table;
+    for key in parents {
+        table = table.get(key)?.as_table_like()?;
+    }
+    table.get(last)
+}
+
+fn remove_key(table: &mut toml::Table, path: &[String]) -> bool {
+    match path {
+        [key] => table.remove(key).is_some(),
+        [key, rest @ ..] => table
+            .get_mut(key)
+            .and_then(toml::Value::as_table_mut)
+            .is_some_and(|table| remove_key(table, rest)),
+        [] => false,
+    }
+}
+
+// Parse config.toml, collecting syntax errors, unknown keys and invalid values.
+// Invalid values are dropped so their defaults apply.
+fn parse_config(file: &str, src: &str) -> (Option<FileConfig>, Vec<Diagnostic>) {
+    let mut checker = ConfigChecker {
+        file,
+        src,
+        diagnostics: Vec::new(),
+    };
+
+    let doc = match Document::parse(src) {
+        Ok(doc) => doc,
+        Err(e) => {
+            checker.report(e.span(), Level::Error, e.message().trim().to_string());
+            return (None, checker.diagnostics);
+        }
+    };
+    // a value of the wrong type or an unknown variant is dropped and the rest read again
+    let mut table: toml::Table = src.parse().unwrap_or_default();
+    let mut current = src.to_string();
+    let mut parsed = loop {
+        let e = match toml::from_str::<FileConfig>(&current) {
+            Ok(parsed) => break parsed,
+            Err(e) => e,
+        };
+        let path = e.span().and_then(|span| {
+            let doc = Document::parse(current.as_str()).ok()?;
+            key_at(doc.as_table(), span.start)
+        });
+        let Some(path) = path.filter(|path| remove_key(&mut table, path)) else {
+            let span = e.span().filter(|_| current == src);
+            checker.report(span, Level::Error, e.message().trim().to_string());
+            return (None, checker.diagnostics);
+        };
+        let span = item_at(doc.as_table(), &path).and_then(Item::span);
+        let msg = format!("invalid '{}': {}", path.join("."), e.message().trim());
+        checker.report(span, Level::Warning, msg);
+        current = toml::to_string(&table).unwrap_or_default();
+    };
+
+    checker.check_keys(doc.as_table(), "", ROOT_KEYS);
+    if let Some(quiz) = doc.get("quiz").and_then(Item::as_table_like) {
+        checker.check_keys(quiz, "quiz", QUIZ_KEYS);
+    }
+
+    if let Some(table) = doc.get("display").and_then(Item::as_table_like)
+        && let Some(d) = parsed.display.as_mut()
+    {
+        checker.check_display(table, "display", d, DISPLAY_KEYS);
+    }
+
+    if let Some(profiles) = doc.get("profile").and_then(Item::as_table_like)
+        && let Some(parsed_profiles) = parsed.profile.as_mut()
+    {
+        let mut known = DISPLAY_KEYS.to_vec();
+        known.push("inherits");
+        for (name, item) in profiles.iter() {
+            if let Some(table) = item.as_table_like()
+                && let Some(p) = parsed_profiles.get_mut(name)
+            {
+                let section = format!("profile.{name}");
+                checker.check_display(table, &section, &mut p.display, &known);
             }
-            if let Some(bc) = d.border_color {
-                r.border_color = bc;
+        }
+    }
+
+    (Some(parsed), checker.diagnostics)
+}
+
+// Path of config.toml: the --config override, $KOTOFETCH_CONFIG or ~/.config/kotofetch/config.toml.
+pub fn config_path(path_override: Option<PathBuf>) -> Option<PathBuf> {
+    if let Some(p) = path_override {
+        Some(p)
+    } else if let Some(p) = env::var_os("KOTOFETCH_CONFIG").filter(|p| !p.is_empty()) {
+        Some(PathBuf::from(p))
+    } else if let Some(mut d) = config_dir() {
+        d.push("kotofetch/config.toml");
+        Some(d)
+    } else {
+        None
+    }
+}
+
+// Replace a leading ~ with the home directory.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+// System-wide configs ($XDG_CONFIG_DIRS/kotofetch/config.toml, /etc/xdg by default),
+// least important first.
+pub fn system_config_paths() -> Vec<PathBuf> {
+    if !cfg!(unix) {
+        return Vec::new();
+    }
+    let dirs = env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+    dirs.split(':')
+        .filter(|d| !d.is_empty())
+        .rev()
+        .map(|d| Path::new(d).join("kotofetch/config.toml"))
+        .collect()
+}
+
+// The closest .kotofetch.toml in the current directory or one of its parents, looking
+// no higher than the home directory or the root of a repository.
+pub fn project_config_path() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    let home = dirs::home_dir();
+    for dir in cwd.ancestors() {
+        let path = dir.join(".kotofetch.toml");
+        if path.is_file() {
+            return Some(path);
+        }
+        if Some(dir) == home.as_deref() || dir.join(".git").exists() {
+            break;
+        }
+    }
+    None
+}
+
+// Whether the user config lists the directory of a project config in `trusted_projects`.
+fn is_trusted_project(layers: &[FileConfig], path: &Path) -> bool {
+    let Some(dir) = path.parent().and_then(|d| d.canonicalize().ok()) else {
+        return false;
+    };
+    layers
+        .iter()
+        .filter(|layer| layer.user)
+        .flat_map(|layer| layer.trusted_projects.iter().flatten())
+        .any(|trusted| expand_tilde(trusted).canonicalize().ok().as_ref() == Some(&dir))
+}
+
+struct Loader {
+    layers: Vec<FileConfig>,
+    problems: usize,
+    // files being loaded, to stop include cycles
+    stack: Vec<PathBuf>,
+}
+
+impl Loader {
+    // Load a config file after the files it includes, reporting every problem
+    // with its line and column. Broken files are skipped.
+    fn load(&mut self, path: &Path, user: bool) {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.stack.contains(&canonical) {
+            eprintln!(
+                "Warning: include cycle, {} is already being loaded, skipping",
+                path.display()
+            );
+            self.problems += 1;
+            return;
+        }
+
+        let src = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Failed to read config {}: {}", path.display(), e);
+                self.problems += 1;
+                return;
             }
-            if let Some(b) = d.source {
-                r.source = b;
+        };
+
+        let file = path.display().to_string();
+        let (parsed, mut diagnostics) = parse_config(&file, &src);
+        diagnostics.sort_by_key(|d| d.location);
+        for d in &diagnostics {
+            d.print();
+        }
+        self.problems += diagnostics.len();
+
+        let Some(mut parsed) = parsed else {
+            eprintln!("{file}: skipping this config");
+            return;
+        };
+
+        // included files come first so this one can override them
+        self.stack.push(canonical);
+        let base = path.parent().unwrap_or(Path::new(""));
+        for include in parsed.include.iter().flatten() {
+            let included = base.join(expand_tilde(include));
+            if included.exists() {
+                self.load(&included, false);
+            } else {
+                eprintln!(
+                    "Warning: {file}: included config not found: {}",
+                    included.display()
+                );
+                self.problems += 1;
             }
-            if let Some(m) = d.modes {
-                r.modes = m.into_iter().map(|s| PathBuf::from(s)).collect();
+        }
+        self.stack.pop();
+
+        // quotes_dirs are relative to the file too
+        let displays = parsed.display.iter_mut().chain(
+            parsed
+                .profile
+                .iter_mut()
+                .flat_map(|profiles| profiles.values_mut().map(|p| &mut p.display)),
+        );
+        for display in displays {
+            for dir in display.quotes_dirs.iter_mut().flatten() {
+                *dir = base.join(expand_tilde(&dir.to_string_lossy()));
             }
-            if let Some(s) = d.seed {
-                r.seed = s;
+        }
+
+        parsed.path = Some(path.to_path_buf());
+        parsed.user = user;
+        self.layers.push(parsed);
+    }
+}
+
+// Load every config layer, least important first: system-wide defaults, the user config
+// and the project-local .kotofetch.toml, each preceded by the files it includes.
+// Broken configs are skipped, unless strict mode turns problems into an error. A
+// project config is only loaded once the user config trusts it.
+pub fn load_config_layers(
+    path_override: Option<PathBuf>,
+    strict: bool,
+) -> Result<Vec<FileConfig>, String> {
+    let mut loader = Loader {
+        layers: Vec::new(),
+        problems: 0,
+        stack: Vec::new(),
+    };
+    let user = config_path(path_override);
+    for path in system_config_paths().into_iter().chain(user.clone()) {
+        if path.exists() {
+            loader.load(&path, Some(&path) == user.as_ref());
+        }
+    }
+    if let Some(path) = project_config_path() {
+        if is_trusted_project(&loader.layers, &path) {
+            loader.load(&path, false);
+        } else {
+            let dir = path.parent().unwrap_or(Path::new("."));
+            eprintln!(
+                "Warning: {} ignored, add \"{}\" to trusted_projects in your config.toml to use it",
+                path.display(),
+                dir.display()
+            );
+        }
+    }
+
+    let strict = strict || loader.layers.iter().any(|c| c.strict == Some(true));
+    if strict && loader.problems > 0 {
+        return Err("invalid config (strict mode)".to_string());
+    }
+    Ok(loader.layers)
+}
+
+// Read a KOTOFETCH_* variable, recording a problem if it can't be parsed.
+fn env_var<T>(
+    name: &str,
+    problems: &mut Vec<String>,
+    parse: impl Fn(&str) -> Option<T>,
+    expected: &str,
+) -> Option<T> {
+    let raw = env::var(name).ok()?;
+    let parsed = parse(raw.trim());
+    if parsed.is_none() {
+        problems.push(format!(
+            "{name}: invalid value '{raw}', expected {expected}"
+        ));
+    }
+    parsed
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.to_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+// Directories separated like $PATH, empty entries skipped.
+fn parse_dirs(s: &str) -> Option<Vec<PathBuf>> {
+    Some(
+        env::split_paths(s)
+            .filter(|d| !d.as_os_str().is_empty())
+            .collect(),
+    )
+}
+
+// Display settings from KOTOFETCH_* environment variables, one per [display] key.
+// Invalid values are reported and ignored, unless strict mode turns them into an error.
+pub fn load_env(strict: bool) -> Result<DisplayConfig, String> {
+    let mut problems = Vec::new();
+    let p = &mut problems;
+    let color = |s: &str| parse_color(s).map(|_| s.to_string());
+    let colors = "a named color or a hex color";
+    let number = "a number";
+    let boolean = "true or false";
+
+    let d = DisplayConfig {
+        horizontal_padding: env_var(
+            "KOTOFETCH_HORIZONTAL_PADDING",
+            p,
+            |s| s.parse().ok(),
+            number,
+        ),
+        vertical_padding: env_var("KOTOFETCH_VERTICAL_PADDING", p, |s| s.parse().ok(), number),
+        width: env_var("KOTOFETCH_WIDTH", p, |s| s.parse().ok(), number),
+        show_translation: env_var(
+            "KOTOFETCH_SHOW_TRANSLATION",
+            p,
+            |s| match s.to_lowercase().as_str() {
+                "none" => Some(TranslationMode::None),
+                "english" => Some(TranslationMode::English),
+                "romaji" => Some(TranslationMode::Romaji),
+                _ => None,
+            },
+            "none, english or romaji",
+        ),
+        translation_color: env_var("KOTOFETCH_TRANSLATION_COLOR", p, color, colors),
+        quote_color: env_var("KOTOFETCH_QUOTE_COLOR", p, color, colors),
+        font_size: env_var(
+            "KOTOFETCH_FONT_SIZE",
+            p,
+            |s| FONT_SIZES.contains(&s).then(|| s.to_string()),
+            "small, medium or large",
+        ),
+        bold: env_var("KOTOFETCH_BOLD", p, parse_bool, boolean),
+        border: env_var("KOTOFETCH_BORDER", p, parse_bool, boolean),
+        rounded_border: env_var("KOTOFETCH_ROUNDED_BORDER", p, parse_bool, boolean),
+        border_color: env_var("KOTOFETCH_BORDER_COLOR", p, color, colors),
+        source: env_var("KOTOFETCH_SOURCE", p, parse_bool, boolean),
+        modes: env_var(
+            "KOTOFETCH_MODES",
+            p,
+            |s| {
+                let modes: Vec<ModeEntry> = s
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|m| !m.is_empty())
+                    .map(|m| ModeEntry::Name(PathBuf::from(m)))
+                    .collect();
+                (!modes.is_empty()).then_some(modes)
+            },
+            "a comma separated list of quote files",
+        ),
+        selection: env_var(
+            "KOTOFETCH_SELECTION",
+            p,
+            |s| match s.to_lowercase().as_str() {
+                "quote" => Some(Selection::Quote),
+                "pack" => Some(Selection::Pack),
+                _ => None,
+            },
+            "quote or pack",
+        ),
+        contextual: env_var("KOTOFETCH_CONTEXTUAL", p, parse_bool, boolean),
+        quotes_dirs: env_var(
+            "KOTOFETCH_QUOTES_DIR",
+            p,
+            parse_dirs,
+            "a list of directories",
+        ),
+        seed: env_var("KOTOFETCH_SEED", p, |s| s.parse().ok(), number),
+        centered: env_var("KOTOFETCH_CENTERED", p, parse_bool, boolean),
+    };
+
+    for problem in &problems {
+        eprintln!("Warning: {problem}");
+    }
+    if strict && !problems.is_empty() {
+        return Err("invalid KOTOFETCH_* environment variables (strict mode)".to_string());
+    }
+    Ok(d)
+}
+
+// The profiles to apply for `name`, from the one it ultimately inherits from to itself.
+fn profile_chain<'a>(
+    profiles: &'a BTreeMap<String, ProfileConfig>,
+    name: &str,
+) -> Result<Vec<(&'a str, &'a ProfileConfig)>, String> {
+    let mut chain: Vec<(&str, &ProfileConfig)> = Vec::new();
+    let mut current = Some(name);
+    while let Some(n) = current {
+        if chain.iter().any(|(c, _)| *c == n) {
+            let names: Vec<&str> = chain.iter().map(|(c, _)| *c).collect();
+            return Err(format!(
+                "profile inheritance cycle: {} -> {n}",
+                names.join(" -> ")
+            ));
+        }
+        let Some((key, profile)) = profiles.get_key_value(n) else {
+            let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+            let hint = match did_you_mean(n, &names) {
+                Some(suggestion) => format!(", did you mean '{suggestion}'?"),
+                None if names.is_empty() => ", no profiles are defined".to_string(),
+                None => format!(", available: {}", names.join(", ")),
+            };
+            return Err(match chain.last() {
+                Some((child, _)) => {
+                    format!("profile '{child}' inherits unknown profile '{n}'{hint}")
+                }
+                None => format!("unknown profile '{n}'{hint}"),
+            });
+        };
+        chain.push((key.as_str(), profile));
+        current = profile.inherits.as_deref();
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+pub fn make_runtime_config(
+    layers: Vec<FileConfig>,
+    env: DisplayConfig,
+    cli: &crate::cli::Cli,
+) -> Result<RuntimeConfig, String> {
+    let mut r = RuntimeConfig::default();
+
+    // --profile wins over $KOTOFETCH_PROFILE
+    let profile = cli
+        .profile
+        .clone()
+        .or_else(|| env::var("KOTOFETCH_PROFILE").ok().filter(|p| !p.is_empty()));
+
+    // apply config files, least important first
+    let mut profiles: BTreeMap<String, ProfileConfig> = BTreeMap::new();
+    for layer in layers {
+        // command packs run from the user's own folders, when the user config says so
+        if layer.user && layer.allow_command_packs == Some(true) {
+            r.command_dirs = vec![user_quotes_dir()];
+            if let Some(dirs) = layer.display.as_ref().and_then(|d| d.quotes_dirs.as_ref()) {
+                r.command_dirs
+                    .extend(dirs.iter().map(|d| expand_tilde(&d.to_string_lossy())));
             }
-            if let Some(c) = d.centered {
-                r.centered = c;
+        }
+        let origin = Origin::File(layer.path.unwrap_or_default());
+        if let Some(d) = layer.display {
+            r.apply_display(d, &origin);
+        }
+        if let Some(q) = layer.quiz {
+            r.apply_quiz(q, &origin);
+        }
+        // profiles with the same name are merged key by key
+        for (name, p) in layer.profile.unwrap_or_default() {
+            match profiles.get_mut(&name) {
+                Some(existing) => {
+                    if p.inherits.is_some() {
+                        existing.inherits = p.inherits;
+                    }
+                    existing.display.overlay(p.display);
+                }
+                None => {
+                    profiles.insert(name, p);
+                }
             }
         }
     }
 
+    // apply the selected profile, after the ones it inherits from
+    if let Some(name) = &profile {
+        for (n, p) in profile_chain(&profiles, name)? {
+            r.apply_display(p.display.clone(), &Origin::Profile(n.to_string()));
+        }
+    }
+
+    // apply KOTOFETCH_* environment variables
+    r.apply_display(env, &Origin::Env);
+
     // apply CLI overrides
-    if let Some(p) = cli.horizontal_padding {
-        r.horizontal_padding = p;
+    r.apply_display(DisplayConfig::from_cli(cli), &Origin::Cli);
+    if !cli.quotes_dir.is_empty() {
+        r.quotes_dirs.splice(0..0, cli.quotes_dir.iter().cloned());
+        r.origins.insert("display.quotes_dirs", Origin::Cli);
     }
-    if let Some(p) = cli.vertical_padding {
-        r.vertical_padding = p;
+    if let Some(crate::cli::Command::Quiz(q)) = &cli.command {
+        r.apply_quiz(QuizConfig::from_cli(q), &Origin::Cli);
     }
-    if let Some(w) = cli.width {
-        r.width = w;
+
+    Ok(r)
+}
+
+// One line description of a setting, used as a comment by `config init`.
+fn describe(section: &str, key: &str) -> &'static str {
+    match (section, key) {
+        ("display", "horizontal_padding") => "Spacing left and right of the quote",
+        ("display", "vertical_padding") => "Spacing above and below the quote",
+        ("display", "width") => "Max width for text wrapping (0 = automatic)",
+        ("display", "show_translation") => "Translation mode: \"none\", \"english\" or \"romaji\"",
+        ("display", "translation_color") => {
+            "Named ANSI color (\"red\", \"dim\", ...) or hex (\"#ffcc00\")"
+        }
+        ("display", "quote_color") => {
+            "Named ANSI color (\"red\", \"dim\", ...) or hex (\"#ffcc00\")"
+        }
+        ("display", "font_size") => {
+            "\"small\", \"medium\" or \"large\" (adds spacing between characters)"
+        }
+        ("display", "bold") => "Bold Japanese text",
+        ("display", "border") => "Show a box border",
+        ("display", "rounded_border") => "Rounded border corners (needs border)",
+        ("display", "border_color") => {
+            "Named ANSI color (\"red\", \"dim\", ...) or hex (\"#ffcc00\")"
+        }
+        ("display", "source") => "Show the quote source",
+        ("display", "modes") => {
+            "Quote files to use, or tables like { name = \"haiku\", weight = 3 } to weight them"
+        }
+        ("display", "selection") => {
+            "\"quote\" (any quote, by weight) or \"pack\" (a pack by weight, then a quote)"
+        }
+        ("display", "contextual") => {
+            "Prefer quotes that fit the season, the time of day and special days"
+        }
+        ("display", "quotes_dirs") => {
+            "Extra directories searched for quote files, before the default ones"
+        }
+        ("display", "seed") => "RNG seed for random quotes (0 = random)",
+        ("display", "centered") => "Center text",
+        ("quiz", "kind") => "What to ask for: \"meaning\", \"reading\" or \"mixed\"",
+        ("quiz", "questions") => "Number of questions",
+        ("quiz", "choices") => "Number of choices for meaning questions",
+        ("quiz", "packs") => "Quote files to draw questions from (defaults to the display modes)",
+        _ => "",
     }
+}
 
-    // map CLI TranslationMode -> config::TranslationMode
-    if let Some(tmode) = &cli.translation {
-        r.show_translation = match tmode {
-            crate::cli::TranslationMode::None => TranslationMode::None,
-            crate::cli::TranslationMode::English => TranslationMode::English,
-            crate::cli::TranslationMode::Romaji => TranslationMode::Romaji,
+// A commented config.toml holding every default value.
+pub fn default_config_toml() -> String {
+    let mut out = String::from(
+        "# kotofetch configuration, generated by `kotofetch config init`.\n\
+         # Every setting below shows its default value, uncomment it to change it.\n\
+         \n\
+         # Fail on any config problem instead of warning and using defaults\n\
+         # strict = false\n\
+         \n\
+         # Run command packs from the quotes folder and the quotes_dirs below\n\
+         # allow_command_packs = false\n\
+         \n\
+         # Directories whose .kotofetch.toml is loaded\n\
+         # trusted_projects = [\"~/src/dotfiles\"]\n",
+    );
+    let defaults = RuntimeConfig::default();
+    let mut section = "";
+    for (sec, key, value) in defaults.entries() {
+        if sec != section {
+            out.push_str(&format!("\n[{sec}]\n"));
+            section = sec;
+        }
+        out.push_str(&format!("# {}\n", describe(sec, key)));
+        match value {
+            Some(v) => out.push_str(&format!("# {key} = {}\n", v.to_string().trim())),
+            // unset lists default to the display modes
+            None => out.push_str(&format!("# {key} = {}\n", paths_value(&defaults.modes))),
+        }
+    }
+    out.push_str(
+        "\n\
+         # Profiles override parts of [display], pick one with --profile <name> or KOTOFETCH_PROFILE.\n\
+         # A profile can start from another one with `inherits`.\n\
+         # [profile.compact]\n\
+         # border = false\n\
+         # vertical_padding = 0\n\
+         # horizontal_padding = 1\n\
+         #\n\
+         # [profile.study]\n\
+         # inherits = \"compact\"\n\
+         # show_translation = \"romaji\"\n",
+    );
+    out
+}
+
+pub fn init(path: &Path, force: bool) -> Result<(), String> {
+    if path.exists() && !force {
+        return Err(format!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        ));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    }
+    fs::write(path, default_config_toml())
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+// Print the effective config, marking where each value came from.
+pub fn show(runtime: &RuntimeConfig) {
+    let dim = Style::new().dim();
+    let mut section = "";
+    for (sec, key, value) in runtime.entries() {
+        if sec != section {
+            if !section.is_empty() {
+                println!();
+            }
+            println!("[{sec}]");
+            section = sec;
+        }
+        let line = match value {
+            Some(v) => format!("{key} = {}", v.to_string().trim()),
+            None => format!("# {key} unset"),
         };
+        let origin = runtime.origin(&format!("{sec}.{key}"));
+        println!("{:<40} {}", line, dim.apply_to(format!("# {origin}")));
+    }
+}
+
+pub fn print_paths(path_override: Option<PathBuf>) {
+    let found = |p: &Path| if p.exists() { "" } else { " (not found)" };
+    for p in system_config_paths() {
+        println!("system:  {}{}", p.display(), found(&p));
+    }
+    match config_path(path_override) {
+        Some(p) => println!("config:  {}{}", p.display(), found(&p)),
+        None => println!("config:  (no config directory)"),
     }
+    match project_config_path() {
+        Some(p) => println!("project: {}", p.display()),
+        None => println!("project: (no .kotofetch.toml here or in a parent directory)"),
+    }
+    let quotes = user_quotes_dir();
+    println!("quotes:  {}{}", quotes.display(), found(&quotes));
+    let cache = cache_dir();
+    println!("cache:   {}{}", cache.display(), found(&cache));
+    let state = state_dir();
+    println!("state:   {}{}", state.display(), found(&state));
+}
 
-    if let Some(tc) = &cli.translation_color {
-        r.translation_color = tc.clone();
+// Open config.toml in $EDITOR, creating it with the defaults first if needed.
+pub fn edit(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        init(path, false)?;
     }
-    if let Some(qc) = &cli.quote_color {
-        r.quote_color = qc.clone();
+    open_in_editor(path)?;
+
+    // report problems right away
+    let src =
+        fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let (_, mut diagnostics) = parse_config(&path.display().to_string(), &src);
+    diagnostics.sort_by_key(|d| d.location);
+    for d in &diagnostics {
+        d.print();
     }
-    if let Some(b) = cli.bold {
-        r.bold = b;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_value_drops_only_its_key() {
+        let src = "[display]\nborder = false\nshow_translation = \"french\"\nwidth = \"wide\"\n";
+        let (parsed, diagnostics) = parse_config("config.toml", src);
+        let display = parsed.unwrap().display.unwrap();
+        assert_eq!(display.border, Some(false));
+        assert!(display.show_translation.is_none());
+        assert!(display.width.is_none());
+
+        let found: Vec<_> = diagnostics.iter().map(|d| (d.level, d.location)).collect();
+        assert_eq!(
+            found,
+            [
+                (Level::Warning, Some((3, 20))),
+                (Level::Warning, Some((4, 9)))
+            ]
+        );
+        assert!(diagnostics[0].message.contains("display.show_translation"));
     }
-    if let Some(b) = cli.border {
-        r.border = b;
+
+    #[test]
+    fn quotes_dir_is_a_path_list() {
+        let dirs = [
+            PathBuf::from("/srv/quotes"),
+            PathBuf::from("/home/me/more quotes"),
+        ];
+        let joined = env::join_paths(&dirs).unwrap();
+        assert_eq!(parse_dirs(joined.to_str().unwrap()), Some(dirs.to_vec()));
+        assert_eq!(
+            parse_dirs("/srv/quotes"),
+            Some(vec![PathBuf::from("/srv/quotes")])
+        );
+        assert_eq!(parse_dirs(""), Some(Vec::new()));
     }
-    if let Some(b) = cli.rounded_border {
-        r.rounded_border = b;
+
+    // A config layer from inline TOML, as the user config.
+    fn layer(src: &str) -> FileConfig {
+        let (parsed, diagnostics) = parse_config("config.toml", src);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        FileConfig {
+            user: true,
+            ..parsed.unwrap()
+        }
     }
-    if let Some(bc) = &cli.border_color {
-        r.border_color = bc.clone();
+
+    fn runtime(layers: Vec<FileConfig>, args: &[&str]) -> Result<RuntimeConfig, String> {
+        let cli = <crate::cli::Cli as clap::Parser>::parse_from(
+            std::iter::once("kotofetch").chain(args.iter().copied()),
+        );
+        let env = toml::from_str::<DisplayConfig>("").unwrap();
+        make_runtime_config(layers, env, &cli)
     }
-    if let Some(b) = cli.source {
-        r.source = b;
+
+    const PROFILES: &str = r#"
+[display]
+width = 40
+border = true
+
+[profile.base]
+border = false
+bold = true
+
+[profile.work]
+inherits = "base"
+width = 60
+bold = false
+"#;
+
+    #[test]
+    fn profile_applies_after_the_one_it_inherits() {
+        let r = runtime(vec![layer(PROFILES)], &["--profile", "work"]).unwrap();
+        assert_eq!((r.width, r.border, r.bold), (60, false, false));
+        assert!(matches!(r.origin("display.width"), Origin::Profile(p) if p == "work"));
+        assert!(matches!(r.origin("display.border"), Origin::Profile(p) if p == "base"));
+
+        let r = runtime(vec![layer(PROFILES)], &["--profile", "base"]).unwrap();
+        assert_eq!((r.width, r.border, r.bold), (40, false, true));
+
+        // the command line still wins over the profile
+        let r = runtime(
+            vec![layer(PROFILES)],
+            &["--profile", "work", "--width", "20"],
+        )
+        .unwrap();
+        assert_eq!(r.width, 20);
     }
 
-    if let Some(cli_modes) = &cli.modes {
-        r.modes = cli_modes.clone();
+    #[test]
+    fn profiles_merge_across_layers() {
+        let later = layer(
+            "[profile.work]
+width = 70
+",
+        );
+        let r = runtime(vec![layer(PROFILES), later], &["--profile", "work"]).unwrap();
+        // the later layer only replaces the keys it sets
+        assert_eq!((r.width, r.border, r.bold), (70, false, false));
     }
 
-    if let Some(s) = cli.seed {
-        r.seed = s;
+    #[test]
+    fn bad_profiles_are_errors() {
+        let error = runtime(vec![layer(PROFILES)], &["--profile", "wrok"]).unwrap_err();
+        assert!(error.contains("did you mean 'work'"), "{error}");
+
+        let cycle = "[profile.a]\ninherits = \"b\"\n\n[profile.b]\ninherits = \"a\"\n";
+        let error = runtime(vec![layer(cycle)], &["--profile", "a"]).unwrap_err();
+        assert!(error.contains("inheritance cycle: a -> b -> a"), "{error}");
     }
-    if let Some(c) = cli.centered {
-        r.centered = c;
+
+    // A fresh directory for a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("kotofetch-{name}-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
     }
 
-    r
+    fn loader() -> Loader {
+        Loader {
+            layers: Vec::new(),
+            problems: 0,
+            stack: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn includes_come_before_the_file() {
+        let dir = test_dir("includes");
+        fs::write(
+            dir.join("config.toml"),
+            "include = [\"colors.toml\", \"size.toml\"]\n[display]\nwidth = 50\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("colors.toml"),
+            "[display]\nwidth = 30\nborder = false\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("size.toml"),
+            "[display]\nwidth = 40\nbold = true\n",
+        )
+        .unwrap();
+
+        let mut loader = loader();
+        loader.load(&dir.join("config.toml"), true);
+        assert_eq!(loader.problems, 0);
+        let names: Vec<_> = loader
+            .layers
+            .iter()
+            .map(|l| l.path.as_ref().unwrap().file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(names, ["colors.toml", "size.toml", "config.toml"]);
+        // only the file itself is the user's, not what it includes
+        let user: Vec<bool> = loader.layers.iter().map(|l| l.user).collect();
+        assert_eq!(user, [false, false, true]);
+
+        let r = runtime(loader.layers, &[]).unwrap();
+        assert_eq!((r.width, r.border, r.bold), (50, false, true));
+    }
+
+    #[test]
+    fn include_cycles_are_cut() {
+        let dir = test_dir("include-cycle");
+        fs::write(
+            dir.join("a.toml"),
+            "include = [\"b.toml\"]\n[display]\nwidth = 10\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("b.toml"),
+            "include = [\"a.toml\"]\n[display]\nwidth = 20\n",
+        )
+        .unwrap();
+
+        let mut loader = loader();
+        loader.load(&dir.join("a.toml"), true);
+        assert_eq!(loader.problems, 1);
+        assert_eq!(loader.layers.len(), 2);
+        let r = runtime(loader.layers, &[]).unwrap();
+        assert_eq!(r.width, 10);
+    }
+
+    #[test]
+    fn missing_include_is_a_problem() {
+        let dir = test_dir("include-missing");
+        fs::write(dir.join("config.toml"), "include = [\"nope.toml\"]\n").unwrap();
+        let mut loader = loader();
+        loader.load(&dir.join("config.toml"), true);
+        assert_eq!((loader.problems, loader.layers.len()), (1, 1));
+    }
+
+    #[test]
+    fn relative_quotes_dirs_follow_their_config() {
+        let dir = test_dir("relative-dirs");
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        fs::write(
+            dir.join("config.toml"),
+            "include = [\"shared/base.toml\"]\n\n[display]\nquotes_dirs = [\"packs\", \"/abs\"]\n\n[profile.work]\nquotes_dirs = [\"work\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("shared/base.toml"),
+            "[display]\nquotes_dirs = [\"../common\"]\n",
+        )
+        .unwrap();
+
+        let mut loader = loader();
+        loader.load(&dir.join("config.toml"), true);
+        assert_eq!(loader.problems, 0);
+        let dirs = |layer: &FileConfig| layer.display.as_ref().unwrap().quotes_dirs.clone();
+        assert_eq!(
+            dirs(&loader.layers[0]),
+            Some(vec![dir.join("shared").join("../common")])
+        );
+        assert_eq!(
+            dirs(&loader.layers[1]),
+            Some(vec![dir.join("packs"), PathBuf::from("/abs")])
+        );
+        let profile = &loader.layers[1].profile.as_ref().unwrap()["work"];
+        assert_eq!(profile.display.quotes_dirs, Some(vec![dir.join("work")]));
+    }
+
+    #[test]
+    fn syntax_error_drops_the_file() {
+        let (parsed, diagnostics) = parse_config("config.toml", "[display\n");
+        assert!(parsed.is_none());
+        assert_eq!(diagnostics[0].level, Level::Error);
+    }
 }
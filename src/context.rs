@@ -0,0 +1,371 @@
+// Contextual selection: quotes may say when they fit with `season`, `time` and `months`,
+// and special days have packs of their own. Everything comes from the local clock.
+use crate::config::{RuntimeConfig, Selection};
+use crate::display::Allowed;
+use crate::quotes::{Pool, Quote, pack_id};
+use rand::Rng;
+use std::fmt;
+use std::path::PathBuf;
+
+pub const SEASONS: &[&str] = &["spring", "summer", "autumn", "winter"];
+pub const TIMES: &[&str] = &["morning", "day", "evening", "night"];
+
+// Special days: name, pack, month and first and last day.
+pub const SPECIAL_DAYS: &[(&str, &str, u32, u32, u32)] = &[
+    ("正月", "shogatsu", 1, 1, 3),
+    ("七夕", "tanabata", 7, 7, 7),
+    ("お盆", "obon", 8, 13, 16),
+];
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// A local date and time.
+#[derive(Debug, Clone, Copy)]
+pub struct Now {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+}
+
+impl Now {
+    #[cfg(unix)]
+    pub fn local() -> Self {
+        // SAFETY: localtime_r only writes to the tm it is given
+        unsafe {
+            let time = libc::time(std::ptr::null_mut());
+            let mut tm: libc::tm = std::mem::zeroed();
+            if libc::localtime_r(&time, &mut tm).is_null() {
+                return Self::utc();
+            }
+            Now {
+                year: tm.tm_year as i64 + 1900,
+                month: tm.tm_mon as u32 + 1,
+                day: tm.tm_mday as u32,
+                hour: tm.tm_hour as u32,
+                minute: tm.tm_min as u32,
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    pub fn local() -> Self {
+        // the layout of Windows' SYSTEMTIME
+        #[repr(C)]
+        #[derive(Default)]
+        struct SystemTime {
+            year: u16,
+            month: u16,
+            day_of_week: u16,
+            day: u16,
+            hour: u16,
+            minute: u16,
+            second: u16,
+            milliseconds: u16,
+        }
+        #[link(name = "kernel32")]
+        unsafe extern "system" {
+            fn GetLocalTime(time: *mut SystemTime);
+        }
+        let mut time = SystemTime::default();
+        // SAFETY: GetLocalTime only writes to the SYSTEMTIME it is given
+        unsafe { GetLocalTime(&mut time) };
+        Now {
+            year: i64::from(time.year),
+            month: u32::from(time.month),
+            day: u32::from(time.day),
+            hour: u32::from(time.hour),
+            minute: u32::from(time.minute),
+        }
+    }
+
+    // without a local clock to ask, seasons and times of day follow UTC
+    #[cfg(not(any(unix, windows)))]
+    pub fn local() -> Self {
+        Self::utc()
+    }
+
+    fn utc() -> Self {
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        // days since 1970-01-01 to a civil date, see
+        // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let days = secs.div_euclid(86400) + 719468;
+        let era = days.div_euclid(146097);
+        let doe = days - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let minutes = secs.rem_euclid(86400) / 60;
+        Now {
+            year: yoe + era * 400 + i64::from(month <= 2),
+            month,
+            day,
+            hour: (minutes / 60) as u32,
+            minute: (minutes % 60) as u32,
+        }
+    }
+
+    // "2026-07-07" (at noon) or "2026-07-07T21:00", also with a space before the time.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid date '{s}', expected YYYY-MM-DD or YYYY-MM-DDTHH:MM");
+        let (date, time) = match s.split_once(['T', ' ']) {
+            Some((date, time)) => (date, Some(time)),
+            None => (s, None),
+        };
+        let mut date = date.splitn(3, '-').map(str::parse::<u32>);
+        let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) =
+            (date.next(), date.next(), date.next())
+        else {
+            return Err(invalid());
+        };
+        let (hour, minute) = match time.map(|t| t.split_once(':')) {
+            None => (12, 0),
+            Some(Some((h, m))) => (
+                h.parse().map_err(|_| invalid())?,
+                m.parse().map_err(|_| invalid())?,
+            ),
+            Some(None) => return Err(invalid()),
+        };
+        if !(1..=12).contains(&month)
+            || !(1..=days_in_month(year, month)).contains(&day)
+            || hour > 23
+            || minute > 59
+        {
+            return Err(invalid());
+        }
+        Ok(Now {
+            year: year as i64,
+            month,
+            day,
+            hour,
+            minute,
+        })
+    }
+
+    pub fn season(&self) -> &'static str {
+        match self.month {
+            3..=5 => "spring",
+            6..=8 => "summer",
+            9..=11 => "autumn",
+            _ => "winter",
+        }
+    }
+
+    pub fn time(&self) -> &'static str {
+        match self.hour {
+            5..=9 => "morning",
+            10..=16 => "day",
+            17..=20 => "evening",
+            _ => "night",
+        }
+    }
+
+    // The special days that fall on this date, by name and pack.
+    pub fn special_days(&self) -> impl Iterator<Item = (&'static str, &'static str)> + '_ {
+        SPECIAL_DAYS
+            .iter()
+            .filter(|(_, _, month, first, last)| {
+                self.month == *month && (*first..=*last).contains(&self.day)
+            })
+            .map(|(name, pack, ..)| (*name, *pack))
+    }
+}
+
+impl fmt::Display for Now {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute
+        )
+    }
+}
+
+// Whether a quote says when it fits.
+fn has_context(quote: &Quote) -> bool {
+    quote.season.is_some() || quote.time.is_some() || quote.months.is_some()
+}
+
+// Whether everything a quote says about when it fits is true `now`.
+fn fits(quote: &Quote, now: &Now) -> bool {
+    quote.season.as_deref().is_none_or(|s| s == now.season())
+        && quote.time.as_deref().is_none_or(|t| t == now.time())
+        && quote.months.as_ref().is_none_or(|m| m.contains(&now.month))
+}
+
+// Index of a random quote among the `allowed` ones, preferring quotes that fit `now`: quotes
+// from the packs of the day and quotes whose season, time and months all match.
+// Without any, quotes that don't say when they fit, then any quote. The usual weights
+// apply among the quotes kept. Every quote of the modes is read.
+pub fn choose(
+    pool: &Pool,
+    modes: &[PathBuf],
+    runtime: &RuntimeConfig,
+    now: &Now,
+    allowed: Allowed,
+    rng: &mut impl Rng,
+) -> Option<usize> {
+    let special: Vec<bool> = modes
+        .iter()
+        .map(|m| now.special_days().any(|(_, pack)| pack_id(m) == pack))
+        .collect();
+
+    // (index, pack, weight) of the quotes of each tier
+    let mut tiers: [Vec<(usize, usize, f64)>; 3] = Default::default();
+    pool.scan(&mut |index, pack, quote| {
+        if !allowed(pack, quote) {
+            return;
+        }
+        let tier = if special[pack] || (has_context(quote) && fits(quote, now)) {
+            0
+        } else if !has_context(quote) {
+            1
+        } else {
+            2
+        };
+        tiers[tier].push((index, pack, quote.weight.unwrap_or(1.0)));
+    });
+    let quotes = tiers.into_iter().find(|t| !t.is_empty())?;
+
+    let pack_weights = runtime.pack_weights();
+    let pack_weight = |i: usize| pack_weights.get(i).copied().unwrap_or(1.0);
+    let quotes = if runtime.selection == Selection::Pack {
+        // a pack by its weight, then one of its quotes
+        let mut packs: Vec<(usize, f64)> = quotes.iter().map(|q| (q.1, pack_weight(q.1))).collect();
+        packs.dedup_by_key(|p| p.0);
+        let pack = pick(rng, &packs)?;
+        quotes
+            .into_iter()
+            .filter(|q| q.1 == pack)
+            .map(|q| (q.0, q.2))
+            .collect()
+    } else {
+        quotes
+            .into_iter()
+            .map(|q| (q.0, q.2 * pack_weight(q.1)))
+            .collect::<Vec<_>>()
+    };
+    pick(rng, &quotes)
+}
+
+// One of the items, as likely as its weight. If the weights add up to nothing
+// usable (all zero, say), every item is as likely.
+fn pick(rng: &mut impl Rng, items: &[(usize, f64)]) -> Option<usize> {
+    let total: f64 = items.iter().map(|i| i.1).sum();
+    if !(total > 0.0 && total.is_finite()) {
+        return items
+            .get(rng.random_range(0..items.len().max(1)))
+            .map(|i| i.0);
+    }
+    let mut point = rng.random_range(0.0..total);
+    let found = items.iter().find(|(_, weight)| {
+        let found = point < *weight;
+        if !found {
+            point -= weight;
+        }
+        found
+    });
+    found.or(items.last()).map(|i| i.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn quote(japanese: &str, season: Option<&str>) -> Quote {
+        Quote {
+            japanese: japanese.to_string(),
+            season: season.map(str::to_string),
+            ..Quote::default()
+        }
+    }
+
+    // How often each quote comes up in 10k contextual picks on Tanabata.
+    fn shares(runtime: &RuntimeConfig, pool: &Pool, modes: &[PathBuf]) -> Vec<f64> {
+        let now = Now::parse("2026-07-07T21:00").unwrap();
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut counts = vec![0; pool.len()];
+        for _ in 0..10_000 {
+            let index = choose(pool, modes, runtime, &now, &|_, _| true, &mut rng).unwrap();
+            counts[index] += 1;
+        }
+        counts.iter().map(|&c| c as f64 / 10_000.0).collect()
+    }
+
+    #[test]
+    fn special_day_pack_is_boosted() {
+        // the pack of the day and the summer quote fit, the others are never picked
+        let pool = Pool::of(vec![
+            vec![quote("織姫", None)],
+            vec![
+                quote("夏", Some("summer")),
+                quote("いつでも", None),
+                quote("冬", Some("winter")),
+            ],
+        ]);
+        let modes = [PathBuf::from("tanabata"), PathBuf::from("proverb")];
+        let mut runtime = RuntimeConfig::default();
+        let equal = shares(&runtime, &pool, &modes);
+        assert!((equal[0] - 0.5).abs() < 0.02, "{equal:?}");
+        assert!((equal[1] - 0.5).abs() < 0.02, "{equal:?}");
+        assert_eq!(equal[2] + equal[3], 0.0);
+
+        // pack weights still apply among the quotes that fit
+        runtime.mode_weights.insert(PathBuf::from("tanabata"), 3.0);
+        runtime.modes = modes.to_vec();
+        let weighted = shares(&runtime, &pool, &modes);
+        assert!((weighted[0] - 0.75).abs() < 0.02, "{weighted:?}");
+        assert!((weighted[1] - 0.25).abs() < 0.02, "{weighted:?}");
+    }
+
+    #[test]
+    fn impossible_dates_are_rejected() {
+        for date in [
+            "2026-02-29",
+            "2026-02-31",
+            "2026-04-31",
+            "2100-02-29",
+            "2026-13-01",
+            "2026-01-00",
+            "2026-01-01T24:00",
+        ] {
+            assert!(Now::parse(date).is_err(), "{date}");
+        }
+        for date in ["2024-02-29", "2000-02-29", "2026-12-31", "2026-04-30T23:59"] {
+            assert!(Now::parse(date).is_ok(), "{date}");
+        }
+    }
+
+    #[test]
+    fn zero_weights_fall_back_to_a_uniform_pick() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let items = [(3, 0.0), (5, 0.0)];
+        let mut seen = [false; 2];
+        for _ in 0..100 {
+            match pick(&mut rng, &items) {
+                Some(3) => seen[0] = true,
+                Some(5) => seen[1] = true,
+                other => panic!("{other:?}"),
+            }
+        }
+        assert_eq!(seen, [true, true]);
+        assert_eq!(pick(&mut rng, &[(7, f64::NAN)]), Some(7));
+        assert_eq!(pick(&mut rng, &[]), None);
+    }
+}
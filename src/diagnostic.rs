@@ -0,0 +1,80 @@
+// Diagnostics with file locations, shared by the config loader and `kotofetch check`.
+use console::Style;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: String,
+    pub location: Option<(usize, usize)>, // 1-based line and column
+    pub level: Level,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn print(&self) {
+        let label = match self.level {
+            Level::Error => Style::new().red().bold().apply_to("error"),
+            Level::Warning => Style::new().yellow().bold().apply_to("warning"),
+        };
+        match self.location {
+            Some((line, col)) => eprintln!(
+                "{}:{}:{}: {}: {}",
+                self.file, line, col, label, self.message
+            ),
+            None => eprintln!("{}: {}: {}", self.file, label, self.message),
+        }
+    }
+}
+
+// 1-based line and column of a byte offset.
+pub fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src[..offset.min(src.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before
+        .rsplit('\n')
+        .next()
+        .map(|l| l.chars().count())
+        .unwrap_or(0)
+        + 1;
+    (line, col)
+}
+
+// Line starts of a source, to turn many offsets into lines and columns without
+// rescanning the source each time.
+pub struct LineIndex<'a> {
+    src: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(src: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { src, starts }
+    }
+
+    // Same as line_col(src, offset).
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.src.len());
+        let line = self.starts.partition_point(|&s| s <= offset);
+        let start = self.starts[line - 1];
+        let col = self.src[start..offset].chars().count() + 1;
+        (line, col)
+    }
+}
+
+// The candidate closest to an unknown word, if it looks like a typo of it.
+pub fn did_you_mean<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates
+        .iter()
+        .map(|c| (strsim::jaro_winkler(word, c), *c))
+        .filter(|(score, _)| *score > 0.8)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, c)| c)
+}
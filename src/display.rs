@@ -1,15 +1,20 @@
-use crate::config::RuntimeConfig;
-use crate::quotes::BUILTIN_QUOTES;
+use crate::cli::OutputFormat;
+use crate::config::{RuntimeConfig, Selection};
+use crate::context::{self, Now};
+use crate::manage::parse_id;
 use crate::quotes::Quote;
-use crate::quotes::QuotesFile;
-use console::{Color, Style};
+use crate::quotes::{Pool, load_cached_pool, pack_id, resolve_pack};
+use crate::state::{Hidden, set_last};
+use console::{Color, Style, measure_text_width};
 use rand::prelude::*;
-use std::fs;
-use term_size;
+use std::path::{Path, PathBuf};
 use textwrap::wrap;
-use unicode_width::UnicodeWidthStr;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-fn simulate_font_size(s: &str, size: &str) -> String {
+// Accepted values of `font_size`.
+pub const FONT_SIZES: &[&str] = &["small", "medium", "large"];
+
+pub fn simulate_font_size(s: &str, size: &str) -> String {
     match size {
         "small" => s.to_string(),
         "medium" => s
@@ -48,55 +53,90 @@ fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
     16 + (36 * r) + (6 * g) + b
 }
 
-fn color_from_hex(s: &str) -> Style {
+// Parse a named color or a hex color (#RRGGBB), None if it isn't one.
+pub fn parse_color(s: &str) -> Option<Style> {
     let lower = s.to_lowercase();
 
     // Named colors
     match lower.as_str() {
-        "black" => return Style::new().fg(Color::Black),
-        "red" => return Style::new().fg(Color::Red),
-        "green" => return Style::new().fg(Color::Green),
-        "yellow" => return Style::new().fg(Color::Yellow),
-        "blue" => return Style::new().fg(Color::Blue),
-        "magenta" => return Style::new().fg(Color::Magenta),
-        "cyan" => return Style::new().fg(Color::Cyan),
-        "white" => return Style::new().fg(Color::White),
-        "dim" => return Style::new().dim(),
+        "black" => return Some(Style::new().fg(Color::Black)),
+        "red" => return Some(Style::new().fg(Color::Red)),
+        "green" => return Some(Style::new().fg(Color::Green)),
+        "yellow" => return Some(Style::new().fg(Color::Yellow)),
+        "blue" => return Some(Style::new().fg(Color::Blue)),
+        "magenta" => return Some(Style::new().fg(Color::Magenta)),
+        "cyan" => return Some(Style::new().fg(Color::Cyan)),
+        "white" => return Some(Style::new().fg(Color::White)),
+        "dim" => return Some(Style::new().dim()),
         _ => {}
     }
 
     // Hex color (#RRGGBB) -> map to nearest 256-color
-    if let Some(stripped) = lower.strip_prefix('#') {
-        if stripped.len() == 6 {
-            if let (Ok(r), Ok(g), Ok(b)) = (
-                u8::from_str_radix(&stripped[0..2], 16),
-                u8::from_str_radix(&stripped[2..4], 16),
-                u8::from_str_radix(&stripped[4..6], 16),
-            ) {
-                let idx = rgb_to_ansi256(r, g, b);
-                return Style::new().fg(Color::Color256(idx));
-            }
-        }
+    if let Some(stripped) = lower.strip_prefix('#')
+        && stripped.len() == 6
+        && let (Ok(r), Ok(g), Ok(b)) = (
+            u8::from_str_radix(&stripped[0..2], 16),
+            u8::from_str_radix(&stripped[2..4], 16),
+            u8::from_str_radix(&stripped[4..6], 16),
+        )
+    {
+        let idx = rgb_to_ansi256(r, g, b);
+        return Some(Style::new().fg(Color::Color256(idx)));
     }
 
-    // Fallback
+    // Any shade of gray is rendered dim
     if lower.contains("gray") || lower.contains("grey") {
-        Style::new().dim()
-    } else {
-        Style::new()
+        return Some(Style::new().dim());
     }
+    None
 }
 
-// Center a whole line in the terminal if `centered` is true.
-fn pad_to_center(line: &str, box_width: usize, centered: bool) -> String {
+// A color as #rrggbb for outputs other than the terminal, named colors as xterm
+// shows them. Dim and gray are a mid gray.
+pub fn color_to_hex(s: &str) -> Option<String> {
+    let lower = s.to_lowercase();
+    let hex = match lower.as_str() {
+        "black" => "#000000",
+        "red" => "#cd0000",
+        "green" => "#00cd00",
+        "yellow" => "#cdcd00",
+        "blue" => "#0000ee",
+        "magenta" => "#cd00cd",
+        "cyan" => "#00cdcd",
+        "white" => "#e5e5e5",
+        "dim" => "#808080",
+        _ if lower.len() == 7
+            && lower.starts_with('#')
+            && lower[1..].chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            return Some(lower);
+        }
+        _ if lower.contains("gray") || lower.contains("grey") => "#808080",
+        _ => return None,
+    };
+    Some(hex.to_string())
+}
+
+fn color_from_hex(s: &str) -> Style {
+    // Fallback
+    parse_color(s).unwrap_or_default()
+}
+
+// Center a whole line in a terminal `term_width` columns wide if `centered` is true.
+fn pad_to_center(
+    line: &str,
+    box_width: usize,
+    centered: bool,
+    term_width: Option<usize>,
+) -> String {
     if !centered {
         return line.to_string();
     }
-    if let Some((term_width, _)) = term_size::dimensions() {
-        if term_width > box_width {
-            let pad = (term_width - box_width) / 2;
-            return format!("{}{}", " ".repeat(pad), line);
-        }
+    if let Some(term_width) = term_width
+        && term_width > box_width
+    {
+        let pad = (term_width - box_width) / 2;
+        return format!("{}{}", " ".repeat(pad), line);
     }
     line.to_string()
 }
@@ -119,186 +159,137 @@ fn align_in_box(line: &str, inner_width: usize, centered: bool) -> String {
     }
 }
 
-// Create an empty line inside the box (used for spacing).
-fn blank_line(
-    inner_width: usize,
-    horizontal_padding: usize,
-    border: bool,
-    border_color: &Style,
-) -> String {
-    if border {
-        format!(
-            "{}{}{}{}{}",
-            border_color.apply_to("│"),
-            " ".repeat(horizontal_padding),
-            " ".repeat(inner_width),
-            " ".repeat(horizontal_padding),
-            border_color.apply_to("│")
-        )
-    } else {
-        " ".repeat(horizontal_padding + inner_width)
+// Cut a line to at most `max_width` columns, ending it with an ellipsis if shortened.
+pub fn truncate_to_width(line: &str, max_width: usize) -> String {
+    if UnicodeWidthStr::width(line) <= max_width {
+        return line.to_string();
+    }
+    if max_width == 0 {
+        return String::new();
     }
-}
 
-fn print_block(
-    lines: &[String],
-    style: Style,
-    inner_width: usize,
-    horizontal_padding: usize,
-    border: bool,
-    box_width: usize,
-    centered: bool,
-    border_color: &Style,
-) {
-    for line in lines {
-        for wline in wrap(line, inner_width) {
-            let content = align_in_box(wline.as_ref(), inner_width, centered);
-            let line = if border {
-                format!(
-                    "{}{}{}{}{}",
-                    border_color.apply_to("│"),
-                    " ".repeat(horizontal_padding),
-                    style.apply_to(content),
-                    " ".repeat(horizontal_padding),
-                    border_color.apply_to("│")
-                )
-            } else {
-                format!(
-                    "{}{}",
-                    " ".repeat(horizontal_padding),
-                    style.apply_to(content)
-                )
-            };
-            println!("{}", pad_to_center(&line, box_width, centered));
+    let mut out = String::new();
+    let mut used = 0;
+    for c in line.chars() {
+        let w = UnicodeWidthChar::width(c).unwrap_or(0);
+        if used + w > max_width - 1 {
+            break;
         }
+        out.push(c);
+        used += w;
     }
+    out.push('…');
+    out
 }
 
-fn print_boxed(
-    text_lines: Vec<String>,
+// A quote ready to be shown with the display settings: its text and how it looks.
+pub struct Layout<'a> {
+    japanese: Vec<String>, // lines, spaced out for the font size
+    text: &'a str,         // as written, for one line
+    translation: Option<&'a str>,
+    source: Option<&'a str>,
     jap_style: Style,
+    translation_style: Style,
+    source_style: Style,
+    border_color: Style,
     horizontal_padding: usize,
     vertical_padding: usize,
     width: usize,
     border: bool,
     rounded_border: bool,
-    border_color: Style,
-    translation: Option<&str>,
-    show_translation: bool,
-    translation_style: Style,
-    source: Option<&str>,
-    show_source: bool,
-    source_style: Style,
     centered: bool,
-) {
-    // Compute max natural width of content
-    let mut max_width = 0;
-    for line in &text_lines {
-        max_width = max_width.max(UnicodeWidthStr::width(line.as_str()));
-    }
-    if show_translation {
-        if let Some(t) = translation {
-            max_width = max_width.max(UnicodeWidthStr::width(t));
+}
+
+impl<'a> Layout<'a> {
+    pub fn new(runtime: &RuntimeConfig, quote: &'a Quote) -> Self {
+        let jap = simulate_font_size(&quote.japanese, &runtime.font_size);
+        let translation = match runtime.show_translation {
+            crate::config::TranslationMode::None => None,
+            crate::config::TranslationMode::English => quote.translation.as_deref(),
+            crate::config::TranslationMode::Romaji => quote.romaji.as_deref(),
+        };
+        let jap_style = if runtime.bold {
+            color_from_hex(&runtime.quote_color).bold()
+        } else {
+            color_from_hex(&runtime.quote_color)
+        };
+        Layout {
+            japanese: jap.lines().map(|s| s.to_string()).collect(),
+            text: &quote.japanese,
+            translation,
+            source: quote.source.as_deref().filter(|_| runtime.source),
+            jap_style,
+            translation_style: color_from_hex(&runtime.translation_color),
+            source_style: Style::new().dim(),
+            border_color: color_from_hex(&runtime.border_color),
+            horizontal_padding: runtime.horizontal_padding,
+            vertical_padding: runtime.vertical_padding,
+            width: runtime.width,
+            border: runtime.border,
+            rounded_border: runtime.rounded_border,
+            centered: runtime.centered,
         }
     }
-    if show_source {
-        if let Some(s) = source {
-            max_width = max_width.max(UnicodeWidthStr::width(s));
+
+    // The width of the text in the box: the longest line, or `width` when set, but no
+    // more than what fits in `max_width` columns with the borders and padding.
+    pub fn inner_width(&self, max_width: Option<usize>) -> usize {
+        let mut natural_width = 0;
+        for line in &self.japanese {
+            natural_width = natural_width.max(UnicodeWidthStr::width(line.as_str()));
+        }
+        if let Some(t) = self.translation {
+            natural_width = natural_width.max(UnicodeWidthStr::width(t));
+        }
+        if let Some(s) = self.source {
+            natural_width = natural_width.max(UnicodeWidthStr::width(s));
         }
-    }
 
-    // Respect user specified width, width <= 0 means automatic
-    let mut inner_width = if width > 0 { width } else { max_width };
+        // Respect user specified width, width <= 0 means automatic
+        let inner_width = if self.width > 0 {
+            self.width
+        } else {
+            natural_width
+        };
+        match max_width {
+            Some(max_width) => inner_width.min(max_width.saturating_sub(self.sides())),
+            None => inner_width,
+        }
+    }
 
-    // Clamp inner width to terminal width minus borders/padding
-    if let Some((term_width, _)) = term_size::dimensions() {
-        let available =
-            term_width.saturating_sub(horizontal_padding * 2 + if border { 2 } else { 0 });
-        inner_width = inner_width.min(available);
+    // Columns taken by the borders and the horizontal padding.
+    pub fn sides(&self) -> usize {
+        self.horizontal_padding * 2 + if self.border { 2 } else { 0 }
     }
 
-    let box_width = inner_width + horizontal_padding * 2 + if border { 2 } else { 0 };
+    // The lines inside the box, wrapped to `inner_width` and aligned in it, with None
+    // for the blank ones (vertical padding included).
+    pub fn rows(&self, inner_width: usize) -> Vec<Option<(Part, String)>> {
+        let mut rows = Vec::new();
+        let block = |rows: &mut Vec<_>, part: Part, lines: Vec<String>| {
+            for line in lines {
+                for wline in wrap(&line, inner_width) {
+                    let content = align_in_box(wline.as_ref(), inner_width, self.centered);
+                    rows.push(Some((part, content)));
+                }
+            }
+        };
 
-    let (top_left, top_right, bottom_left, bottom_right) = if rounded_border {
-        ('╭', '╮', '╰', '╯')
-    } else {
-        ('┌', '┐', '└', '┘')
-    };
-    let horiz = "─";
-
-    // Top border
-    if border {
-        let line = format!(
-            "{}{}{}",
-            top_left,
-            horiz.repeat(inner_width + horizontal_padding * 2),
-            top_right
-        );
-        println!(
-            "{}",
-            border_color.apply_to(pad_to_center(&line, box_width, centered))
-        );
-    }
+        // Vertical padding (top)
+        rows.extend((0..self.vertical_padding).map(|_| None));
 
-    // Vertical padding (top)
-    for _ in 0..vertical_padding {
-        println!(
-            "{}",
-            pad_to_center(
-                &blank_line(inner_width, horizontal_padding, border, &border_color),
-                box_width,
-                centered
-            )
-        );
-    }
+        // Japanese text
+        block(&mut rows, Part::Japanese, self.japanese.clone());
 
-    // Japanese text
-    print_block(
-        &text_lines,
-        jap_style,
-        inner_width,
-        horizontal_padding,
-        border,
-        box_width,
-        centered,
-        &border_color,
-    );
-
-    // Translation
-    if show_translation {
-        if let Some(t) = translation {
-            println!(
-                "{}",
-                pad_to_center(
-                    &blank_line(inner_width, horizontal_padding, border, &border_color),
-                    box_width,
-                    centered
-                )
-            );
-            print_block(
-                &[t.to_string()],
-                translation_style,
-                inner_width,
-                horizontal_padding,
-                border,
-                box_width,
-                centered,
-                &border_color,
-            );
+        // Translation
+        if let Some(t) = self.translation {
+            rows.push(None);
+            block(&mut rows, Part::Translation, vec![t.to_string()]);
         }
-    }
 
-    // Source
-    if show_source {
-        if let Some(s) = source {
-            println!(
-                "{}",
-                pad_to_center(
-                    &blank_line(inner_width, horizontal_padding, border, &border_color),
-                    box_width,
-                    centered
-                )
-            );
+        // Source
+        if let Some(s) = self.source {
+            rows.push(None);
             let wrapped: Vec<String> = wrap(s, inner_width.saturating_sub(2))
                 .into_iter()
                 .enumerate()
@@ -310,154 +301,560 @@ fn print_boxed(
                     }
                 })
                 .collect();
-            print_block(
-                &wrapped,
-                source_style,
-                inner_width,
-                horizontal_padding,
-                border,
-                box_width,
-                centered,
-                &border_color,
-            );
+            block(&mut rows, Part::Source, wrapped);
         }
+
+        // Vertical padding (bottom)
+        rows.extend((0..self.vertical_padding).map(|_| None));
+        rows
     }
 
-    // Vertical padding (bottom)
-    for _ in 0..vertical_padding {
-        println!(
-            "{}",
-            pad_to_center(
-                &blank_line(inner_width, horizontal_padding, border, &border_color),
-                box_width,
-                centered
-            )
-        );
+    // The rows that fit in `max_lines` with the borders, the last one kept ending with an
+    // ellipsis when some are left out.
+    pub fn cut_rows(
+        &self,
+        inner_width: usize,
+        max_lines: Option<usize>,
+    ) -> Vec<Option<(Part, String)>> {
+        let mut rows = self.rows(inner_width);
+        let borders = if self.border { 2 } else { 0 };
+        let Some(room) = max_lines.map(|max| max.saturating_sub(borders)) else {
+            return rows;
+        };
+        if rows.len() <= room {
+            return rows;
+        }
+        rows.truncate(room);
+        if let Some(Some((_, content))) = rows.last_mut() {
+            let text = if self.centered {
+                content.trim()
+            } else {
+                content.trim_end()
+            };
+            let cut = truncate_to_width(&format!("{text}…"), inner_width);
+            *content = align_in_box(&cut, inner_width, self.centered);
+        }
+        rows
     }
 
-    // Bottom border
-    if border {
-        let line = format!(
-            "{}{}{}",
-            bottom_left,
-            horiz.repeat(inner_width + horizontal_padding * 2),
-            bottom_right
-        );
-        println!(
-            "{}",
-            border_color.apply_to(pad_to_center(&line, box_width, centered))
-        );
+    pub fn style(&self, part: Part) -> Style {
+        match part {
+            Part::Japanese => self.jap_style.clone(),
+            Part::Separator => Style::new(),
+            Part::Translation => self.translation_style.clone(),
+            Part::Source => self.source_style.clone(),
+        }
     }
-}
 
-pub fn render(runtime: &RuntimeConfig, cli: &crate::cli::Cli) {
-    // seed
-    let seed = if cli.seed.unwrap_or(0) == 0 {
-        rand::random::<u64>()
-    } else {
-        cli.seed.unwrap_or(runtime.seed)
-    };
-    let mut rng = StdRng::seed_from_u64(seed);
-
-    // collect quotes
-    let mut pool = Vec::new();
-    for mode_file in &runtime.modes {
-        // Ensure the file has .toml extension
-        let mut file_name = mode_file.clone();
-        if file_name.extension().is_none() {
-            file_name.set_extension("toml");
+    // The quote in its box, at most `max_width` columns wide and `max_lines` lines high
+    // when set, and centered in a terminal `term_width` columns wide when known.
+    pub fn boxed(
+        &self,
+        max_width: Option<usize>,
+        term_width: Option<usize>,
+        max_lines: Option<usize>,
+    ) -> Vec<String> {
+        // Clamp inner width to the space available minus borders/padding
+        let inner_width = [term_width, max_width]
+            .into_iter()
+            .flatten()
+            .fold(self.inner_width(max_width), |width, available| {
+                width.min(available.saturating_sub(self.sides()))
+            });
+        let box_width = inner_width + self.sides();
+
+        let (top_left, top_right, bottom_left, bottom_right) = if self.rounded_border {
+            ('╭', '╮', '╰', '╯')
+        } else {
+            ('┌', '┐', '└', '┘')
+        };
+        let horiz = "─";
+        let padding = " ".repeat(self.horizontal_padding);
+
+        let mut lines = Vec::new();
+
+        // Top border
+        if self.border {
+            lines.push(format!(
+                "{}{}{}",
+                top_left,
+                horiz.repeat(inner_width + self.horizontal_padding * 2),
+                top_right
+            ));
+        }
+
+        for row in self.cut_rows(inner_width, max_lines) {
+            let content = match row {
+                Some((part, content)) => self.style(part).apply_to(content).to_string(),
+                None => " ".repeat(inner_width),
+            };
+            lines.push(if self.border {
+                format!(
+                    "{}{padding}{content}{padding}{}",
+                    self.border_color.apply_to("│"),
+                    self.border_color.apply_to("│")
+                )
+            } else {
+                format!("{padding}{content}")
+            });
         }
 
-        // Look in ~/.config/kotofetch/quotes first
-        let mut path = dirs::config_dir().unwrap_or_default();
-        path.push("kotofetch/quotes");
-        path.push(&file_name);
+        // Bottom border
+        if self.border {
+            lines.push(format!(
+                "{}{}{}",
+                bottom_left,
+                horiz.repeat(inner_width + self.horizontal_padding * 2),
+                bottom_right
+            ));
+        }
 
-        if path.exists() {
-            if let Ok(s) = fs::read_to_string(&path) {
-                match toml::from_str::<QuotesFile>(&s) {
-                    Ok(parsed) => pool.extend(parsed.quotes),
-                    Err(e) => eprintln!("Failed to parse {}: {e}", path.display()),
+        let last = lines.len().saturating_sub(1);
+        lines
+            .into_iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let line = pad_to_center(&line, box_width, self.centered, term_width);
+                // the borders are colored whole, with the centering
+                if self.border && (i == 0 || i == last) {
+                    self.border_color.apply_to(line).to_string()
+                } else {
+                    line
                 }
+            })
+            .collect()
+    }
+
+    // The quote on a single line, like "逃げちゃダメだ — You mustn't run away.", cut
+    // with an ellipsis to `max_width` columns when set.
+    pub fn oneline(&self, max_width: Option<usize>) -> String {
+        self.oneline_parts(max_width)
+            .into_iter()
+            .map(|(part, text)| self.style(part).apply_to(text).to_string())
+            .collect()
+    }
+
+    // The text of `oneline`, part by part, for outputs styling it their own way.
+    pub fn oneline_parts(&self, max_width: Option<usize>) -> Vec<(Part, String)> {
+        let join = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut parts = vec![(Part::Japanese, join(self.text))];
+        if let Some(t) = self.translation {
+            parts.push((Part::Separator, " — ".to_string()));
+            parts.push((Part::Translation, join(t)));
+        }
+        if let Some(s) = self.source {
+            parts.push((Part::Source, format!(" ({})", join(s))));
+        }
+
+        let text: String = parts.iter().map(|(_, text)| text.as_str()).collect();
+        let text = match max_width {
+            Some(max_width) => truncate_to_width(&text, max_width),
+            None => text,
+        };
+        let mut chars = text.chars();
+        // split the kept text again, the ellipsis goes with the part it cuts
+        let last = parts.len() - 1;
+        let mut kept_parts = Vec::new();
+        for (i, (part, text)) in parts.into_iter().enumerate() {
+            let count = if i == last {
+                usize::MAX
             } else {
-                eprintln!("Failed to read file: {}", path.display());
+                text.chars().count()
+            };
+            let kept: String = chars.by_ref().take(count).collect();
+            if kept.is_empty() {
+                break;
             }
-            continue; // skip built-in if config exists
+            kept_parts.push((part, kept));
         }
+        kept_parts
+    }
+}
 
-        // fallback to built-in
-        let file_str = file_name.to_str().unwrap_or_default();
-        if let Some((_, content)) = BUILTIN_QUOTES
-            .iter()
-            .find(|&&(name, _)| name == file_name.to_str().unwrap())
-        {
-            match toml::from_str::<QuotesFile>(content) {
-                Ok(parsed) => pool.extend(parsed.quotes),
-                Err(e) => eprintln!("Failed to parse built-in {}: {e}", file_str),
+// The pieces of a quote on one line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Part {
+    Japanese,
+    Separator,
+    Translation,
+    Source,
+}
+
+// The quote that was shown: its pack (as given in the modes), its ID in the pack and
+// the seed in use. Printed as a replay token, like "anime:3f9a0c1e@1234".
+#[derive(Debug)]
+struct Pick {
+    pack: PathBuf,
+    key: String,
+    seed: u64,
+}
+
+// Hash IDs in replay tokens are long enough to stay unique as packs grow.
+const TOKEN_ID_LEN: usize = 8;
+
+impl Pick {
+    fn id(&self) -> String {
+        format!("{}:{}", pack_id(&self.pack), self.key)
+    }
+
+    fn token(&self) -> String {
+        format!("{}@{}", self.id(), self.seed)
+    }
+
+    fn parse(token: &str) -> Result<Self, String> {
+        let (id, seed) = token.rsplit_once('@').ok_or_else(|| {
+            format!("invalid replay token '{token}', expected <pack>:<id>@<seed>")
+        })?;
+        let seed = seed
+            .parse()
+            .map_err(|_| format!("invalid seed in replay token '{token}'"))?;
+        let (pack, key) = parse_id(id)?;
+        Ok(Pick { pack, key, seed })
+    }
+}
+
+// The quote `key` names in `pack`, which is read directly wherever it is in the modes.
+fn find_quote(
+    pack: &Path,
+    key: &str,
+    search: &[PathBuf],
+    use_cache: bool,
+) -> Result<Quote, String> {
+    let pool = load_cached_pool(std::slice::from_ref(&pack.to_path_buf()), search, use_cache);
+    let index = pool.find(0, key, &pack_id(pack))?;
+    pool.get(index)
+        .ok_or_else(|| format!("failed to read {}:{key}", pack_id(pack)))
+}
+
+// The quote to show, and where it comes from unless the pool is empty.
+fn pick_quote(
+    runtime: &RuntimeConfig,
+    cli: &crate::cli::Cli,
+    seed: u64,
+    now: &Now,
+) -> Result<(Quote, Option<Pick>), String> {
+    let search = runtime.quote_search_path();
+    let use_cache = !cli.no_cache;
+
+    if let Some(token) = &cli.replay {
+        let pick = Pick::parse(token)?;
+        let quote = find_quote(&pick.pack, &pick.key, &search, use_cache)?;
+        return Ok((quote, Some(pick)));
+    }
+    if let Some(id) = &cli.id {
+        let (pack, key) = parse_id(id)?;
+        let quote = find_quote(&pack, &key, &search, use_cache)?;
+        let key = quote.short_id(TOKEN_ID_LEN);
+        return Ok((quote, Some(Pick { pack, key, seed })));
+    }
+
+    // the packs of the day come with the modes
+    let mut modes = runtime.modes.clone();
+    if runtime.contextual {
+        for (_, pack) in now.special_days() {
+            let pack = PathBuf::from(pack);
+            if !modes.iter().any(|m| pack_id(m) == pack_id(&pack))
+                && resolve_pack(&pack, &search).is_some()
+            {
+                modes.push(pack);
             }
-        } else {
-            eprintln!(
-                "Warning: mode file not found in config or built-in: {}",
-                file_str
-            );
         }
     }
-
+    let pool = load_cached_pool(&modes, &search, use_cache);
     if pool.is_empty() {
-        pool.push(Quote {
+        let quote = Quote {
             japanese: "(no quote found)".to_string(),
-            translation: None,
-            romaji: None,
-            source: None,
-        });
+            ..Default::default()
+        };
+        return Ok((quote, None));
     }
+    let index = match cli.index {
+        Some(i) if i >= pool.len() => {
+            return Err(format!(
+                "--index {i} is out of range, the modes have {} quotes",
+                pool.len()
+            ));
+        }
+        Some(i) => i,
+        None => {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let hidden = Hidden::load();
+            let visible = |pack: usize, quote: &Quote| !hidden.contains(&modes[pack], quote);
+            let fitting =
+                |pack: usize, quote: &Quote| visible(pack, quote) && fits(runtime, cli, quote);
+            let visible: Option<Allowed> = (!hidden.is_empty()).then_some(&visible);
+            // when no quote fits, one is cut to the space instead
+            let fitting = if cli.max_width.is_some() || cli.max_lines.is_some() {
+                choose_allowed(&pool, &modes, runtime, now, &mut rng, Some(&fitting))
+            } else {
+                None
+            };
+            fitting
+                .or_else(|| choose_allowed(&pool, &modes, runtime, now, &mut rng, visible))
+                .ok_or("every quote of the modes is hidden, see `kotofetch hide`")?
+        }
+    };
+    let quote = pool
+        .get(index)
+        .ok_or_else(|| format!("failed to read quote {index}"))?;
+    let pick = pool.locate(index).map(|(pack, _)| Pick {
+        pack: modes[pack].clone(),
+        key: quote.short_id(TOKEN_ID_LEN),
+        seed,
+    });
+    Ok((quote, pick))
+}
 
-    // pick quote
-    let quote = if let Some(i) = cli.index {
-        pool.get(i).cloned()
+// Whether a quote, as shown, fits within --max-width and --max-lines.
+fn fits(runtime: &RuntimeConfig, cli: &crate::cli::Cli, quote: &Quote) -> bool {
+    let layout = Layout::new(runtime, quote);
+    let lines = if cli.oneline || cli.format.one_line() {
+        vec![layout.oneline(None)]
     } else {
-        pool.choose(&mut rng).cloned()
-    }
-    .unwrap();
+        layout.boxed(cli.max_width, None, None)
+    };
+    cli.max_lines.is_none_or(|max| lines.len() <= max)
+        && cli
+            .max_width
+            .is_none_or(|max| lines.iter().all(|l| measure_text_width(l) <= max))
+}
 
-    // render
-    let jap = simulate_font_size(&quote.japanese, &runtime.font_size);
-    let jap_lines: Vec<String> = jap.lines().map(|s| s.to_string()).collect();
-    let translation_style = color_from_hex(&runtime.translation_color);
-    let show_source = runtime.source && quote.source.is_some();
-    let source_style = Style::new().dim();
+// Whether a quote of the pack at this position in the modes may be picked.
+pub type Allowed<'a> = &'a dyn Fn(usize, &Quote) -> bool;
+
+// How many times a quote ruled out is drawn again before looking for the others.
+const RULED_OUT_DRAWS: usize = 32;
+
+// A random quote among the `allowed` ones, any when None. Quotes ruled out are drawn
+// again, so hiding a quote doesn't change what the other seeds give. If they keep
+// coming, one of the quotes left is picked, all as likely. Contextual selection has
+// its own way, see context::choose.
+fn choose_allowed(
+    pool: &Pool,
+    modes: &[PathBuf],
+    runtime: &RuntimeConfig,
+    now: &Now,
+    rng: &mut StdRng,
+    allowed: Option<Allowed>,
+) -> Option<usize> {
+    let by_pack = runtime.selection == Selection::Pack;
+    if runtime.contextual {
+        return context::choose(
+            pool,
+            modes,
+            runtime,
+            now,
+            allowed.unwrap_or(&|_, _| true),
+            rng,
+        );
+    }
+    let Some(allowed) = allowed else {
+        return pool.choose(rng, &runtime.pack_weights(), by_pack);
+    };
+    for _ in 0..RULED_OUT_DRAWS {
+        let index = pool.choose(rng, &runtime.pack_weights(), by_pack)?;
+        if let (Some((pack, _)), Some(quote)) = (pool.locate(index), pool.get(index))
+            && allowed(pack, &quote)
+        {
+            return Some(index);
+        }
+    }
+    let mut left = Vec::new();
+    pool.scan(&mut |index, pack, quote| {
+        if allowed(pack, quote) {
+            left.push(index);
+        }
+    });
+    left.into_iter().choose(rng)
+}
 
-    let (translation, show_translation) = match runtime.show_translation {
-        crate::config::TranslationMode::None => (None, false),
-        crate::config::TranslationMode::English => {
-            (quote.translation.as_deref(), quote.translation.is_some())
+// How the quote was chosen, for --explain.
+fn explain(
+    runtime: &RuntimeConfig,
+    cli: &crate::cli::Cli,
+    seed: u64,
+    now: &Now,
+    pick: Option<&Pick>,
+) {
+    let seed_origin = if cli.replay.is_some() {
+        "replay token".to_string()
+    } else if runtime.seed == 0 {
+        "random".to_string()
+    } else {
+        runtime.origin("display.seed").to_string()
+    };
+    eprintln!("seed:      {seed} ({seed_origin})");
+    if cli.replay.is_none() && cli.index.is_none() && cli.id.is_none() {
+        eprintln!("selection: {}", runtime.selection.as_str());
+        if runtime.contextual {
+            let days: Vec<&str> = now.special_days().map(|(name, _)| name).collect();
+            let days = if days.is_empty() {
+                String::new()
+            } else {
+                format!(", {}", days.join(", "))
+            };
+            eprintln!("context:   {now} ({} {}{days})", now.season(), now.time());
         }
-        crate::config::TranslationMode::Romaji => (quote.romaji.as_deref(), quote.romaji.is_some()),
+    }
+    let Some(pick) = pick else {
+        return;
     };
+    match resolve_pack(&pick.pack, &runtime.quote_search_path()) {
+        Some(source) => eprintln!("pack:      {} ({source})", pack_id(&pick.pack)),
+        None => eprintln!("pack:      {}", pack_id(&pick.pack)),
+    }
+    eprintln!("quote:     {}", pick.id());
+    eprintln!("replay:    {}", pick.token());
+}
+
+// The seed to pick with, a replay token carries its own.
+fn seed(runtime: &RuntimeConfig, cli: &crate::cli::Cli) -> Result<u64, String> {
+    Ok(match &cli.replay {
+        Some(token) => Pick::parse(token)?.seed,
+        None if runtime.seed == 0 => rand::random::<u64>(),
+        None => runtime.seed,
+    })
+}
 
-    let jap_style = if runtime.bold {
-        color_from_hex(&runtime.quote_color).bold()
+// The quote `render` would show, for outputs laying it out their own way.
+pub fn choose_quote(runtime: &RuntimeConfig, cli: &crate::cli::Cli) -> Result<Quote, String> {
+    let now = cli.date.unwrap_or_else(Now::local);
+    pick_quote(runtime, cli, seed(runtime, cli)?, &now).map(|(quote, _)| quote)
+}
+
+pub fn render(runtime: &RuntimeConfig, cli: &crate::cli::Cli) -> Result<(), String> {
+    let seed = seed(runtime, cli)?;
+    let now = cli.date.unwrap_or_else(Now::local);
+    let (quote, pick) = pick_quote(runtime, cli, seed, &now)?;
+    if let Some(pick) = &pick {
+        set_last(&pick.pack, &quote);
+    }
+
+    let layout = Layout::new(runtime, &quote);
+    let term_width = term_size::dimensions().map(|(w, _)| w);
+    let max_width = cli.max_width.or(term_width);
+    if cli.format.one_line() {
+        let pack = pick.as_ref().map(|p| p.pack.as_path());
+        let line = crate::output::format(runtime, cli.format, &quote, pack, cli.max_width);
+        println!("{line}");
+    } else if cli.format == OutputFormat::Svg {
+        print!(
+            "{}",
+            crate::graphics::svg(runtime, &layout, cli.max_width, cli.max_lines)
+        );
+    } else if cli.format == OutputFormat::Html {
+        print!(
+            "{}",
+            crate::graphics::html(runtime, &layout, cli.max_width, cli.max_lines)
+        );
+    } else if cli.oneline {
+        println!("{}", layout.oneline(max_width));
     } else {
-        color_from_hex(&runtime.quote_color)
-    };
+        for line in layout.boxed(cli.max_width, term_width, cli.max_lines) {
+            println!("{line}");
+        }
+    }
+
+    if cli.explain {
+        explain(runtime, cli, seed, &now, pick.as_ref());
+    } else if cli.print_seed {
+        eprintln!("seed: {seed}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_lines_keeps_the_borders() {
+        let runtime = RuntimeConfig::default();
+        let quote = Quote {
+            japanese: "春雨や\n川面を濡らす\n小舟かな".to_string(),
+            translation: Some("Spring rain".to_string()),
+            ..Quote::default()
+        };
+        let layout = Layout::new(&runtime, &quote);
+        let lines = layout.boxed(None, None, Some(5));
+        assert_eq!(lines.len(), 5);
+        assert!(console::strip_ansi_codes(&lines[4]).starts_with('╰'));
+        assert!(console::strip_ansi_codes(&lines[3]).contains('…'));
+        // a box that fits isn't touched
+        assert_eq!(
+            layout.boxed(None, None, Some(100)),
+            layout.boxed(None, None, None)
+        );
+    }
+
+    // A pack of a few quotes in a fresh directory, as the only mode.
+    fn replay_runtime() -> RuntimeConfig {
+        let dir = std::env::temp_dir().join(format!("kotofetch-replay-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pack = dir.join("sayings.toml");
+        let quotes: String = [
+            "猫に小判",
+            "花より団子",
+            "一期一会",
+            "七転び八起き",
+            "猿も木から落ちる",
+        ]
+        .iter()
+        .map(|j| format!("[[quote]]\njapanese = \"{j}\"\n\n"))
+        .collect();
+        std::fs::write(&pack, quotes).unwrap();
+        RuntimeConfig {
+            modes: vec![pack],
+            ..RuntimeConfig::default()
+        }
+    }
+
+    fn cli(args: &[&str]) -> crate::cli::Cli {
+        <crate::cli::Cli as clap::Parser>::parse_from(
+            ["kotofetch", "--no-cache"].iter().chain(args).copied(),
+        )
+    }
 
-    let border_color = color_from_hex(&runtime.border_color);
-
-    print_boxed(
-        jap_lines,
-        jap_style,
-        runtime.horizontal_padding,
-        runtime.vertical_padding,
-        runtime.width,
-        runtime.border,
-        runtime.rounded_border,
-        border_color,
-        translation,
-        show_translation,
-        translation_style,
-        quote.source.as_deref(),
-        show_source,
-        source_style,
-        runtime.centered,
-    );
+    #[test]
+    fn replay_token_gives_the_same_quote() {
+        let runtime = replay_runtime();
+        let now = Now::parse("2026-03-01").unwrap();
+        for seed in [1, 7, 42, 1234] {
+            let (quote, pick) = pick_quote(&runtime, &cli(&[]), seed, &now).unwrap();
+            let token = pick.unwrap().token();
+            assert!(token.ends_with(&format!("@{seed}")), "{token}");
+
+            let (replayed, pick) =
+                pick_quote(&runtime, &cli(&["--replay", &token]), 0, &now).unwrap();
+            assert_eq!(replayed, quote);
+            assert_eq!(pick.unwrap().token(), token);
+        }
+    }
+
+    #[test]
+    fn malformed_replay_tokens_are_errors() {
+        for token in [
+            "sayings",
+            "sayings:abcd",
+            "sayings:abcd@",
+            "sayings:abcd@x",
+            ":abcd@1",
+            "sayings:@1",
+        ] {
+            assert!(Pick::parse(token).is_err(), "{token}");
+        }
+        let pick = Pick::parse("a/b:c:3f9a0c1e@18446744073709551615").unwrap();
+        assert_eq!(
+            (pick.pack, pick.key.as_str(), pick.seed),
+            (PathBuf::from("a/b:c"), "3f9a0c1e", u64::MAX)
+        );
+
+        // a token naming no quote of the pack
+        let runtime = replay_runtime();
+        let now = Now::parse("2026-03-01").unwrap();
+        let pack = runtime.modes[0].display().to_string();
+        let token = format!("{pack}:ffffffff@1");
+        let error = pick_quote(&runtime, &cli(&["--replay", &token]), 0, &now).unwrap_err();
+        assert!(error.starts_with("no quote found"), "{error}");
+    }
 }
@@ -0,0 +1,311 @@
+// Export quote packs to other formats.
+use crate::cli::{ExportArgs, ExportFormat};
+use crate::config::RuntimeConfig;
+use crate::manage::read_pack;
+use crate::quotes::{Quote, pack_name};
+use std::fs;
+use std::io::{self, Write};
+
+// Quotes grouped by the pack they come from.
+type Packs = Vec<(String, Vec<Quote>)>;
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Anki's plain text import, with the pack as a tag so imported notes can be found again.
+fn anki_tsv(packs: &Packs) -> String {
+    let field = |s: &str| escape_html(s).replace('\t', " ").replace('\n', "<br>");
+    let mut out = String::from(
+        "#separator:tab\n#html:true\n#columns:Japanese\tTranslation\tRomaji\tSource\tTags\n#tags column:5\n",
+    );
+    for (pack, quotes) in packs {
+        let tag = format!("kotofetch::{}", pack.replace(' ', "_"));
+        for q in quotes {
+            let optional = |s: &Option<String>| s.as_deref().map(field).unwrap_or_default();
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                field(&q.japanese),
+                optional(&q.translation),
+                optional(&q.romaji),
+                optional(&q.source),
+                tag
+            ));
+        }
+    }
+    out
+}
+
+fn markdown(packs: &Packs) -> String {
+    let escape = |s: &str| {
+        s.replace('\\', "\\\\")
+            .replace('*', "\\*")
+            .replace('_', "\\_")
+    };
+    // two trailing spaces keep the line breaks
+    let lines = |s: &str, prefix: &str| {
+        s.lines()
+            .map(|l| format!("{prefix}{}", escape(l.trim())))
+            .collect::<Vec<_>>()
+            .join("  \n")
+    };
+
+    let mut out = String::new();
+    for (pack, quotes) in packs {
+        out.push_str(&format!("# {}\n\n", escape(pack)));
+        for q in quotes {
+            out.push_str(&lines(&q.japanese, "> "));
+            out.push_str("\n\n");
+            let mut details = Vec::new();
+            if let Some(t) = &q.translation {
+                details.push(format!("*{}*", lines(t, "")));
+            }
+            if let Some(r) = &q.romaji {
+                details.push(lines(r, ""));
+            }
+            if let Some(s) = &q.source {
+                details.push(format!("— {}", escape(s)));
+            }
+            if !details.is_empty() {
+                out.push_str(&details.join("  \n"));
+                out.push_str("\n\n");
+            }
+        }
+    }
+    out
+}
+
+const HTML_STYLE: &str = "
+  body { font-family: 'Noto Serif JP', 'Hiragino Mincho ProN', 'Yu Mincho', serif;
+         max-width: 40em; margin: 3em auto; padding: 0 1em; color: #222; line-height: 1.6; }
+  h1 { font-weight: normal; text-align: center; letter-spacing: 0.2em;
+       border-bottom: 1px solid #ccc; padding-bottom: 0.5em; }
+  figure { margin: 2.5em 0; text-align: center; break-inside: avoid; }
+  blockquote { margin: 0; font-size: 1.6em; }
+  figcaption p { margin: 0.3em 0; }
+  .translation { font-style: italic; }
+  .romaji { color: #666; }
+  .source { color: #888; font-size: 0.9em; }
+  @media print {
+    body { margin: 0; max-width: none; }
+    section + section { break-before: page; }
+  }
+  @page { margin: 2cm; }
+";
+
+// A standalone page, one section per pack.
+fn html(packs: &Packs) -> String {
+    let lines = |s: &str| {
+        s.lines()
+            .map(|l| escape_html(l.trim()))
+            .collect::<Vec<_>>()
+            .join("<br>")
+    };
+    let title = packs
+        .iter()
+        .map(|(p, _)| p.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{}</title>\n<style>{HTML_STYLE}</style>\n</head>\n<body>\n",
+        escape_html(&title)
+    );
+    for (pack, quotes) in packs {
+        out.push_str(&format!("<section>\n<h1>{}</h1>\n", escape_html(pack)));
+        for q in quotes {
+            out.push_str("<figure>\n");
+            out.push_str(&format!(
+                "  <blockquote lang=\"ja\">{}</blockquote>\n",
+                lines(&q.japanese)
+            ));
+            out.push_str("  <figcaption>\n");
+            if let Some(t) = &q.translation {
+                out.push_str(&format!("    <p class=\"translation\">{}</p>\n", lines(t)));
+            }
+            if let Some(r) = &q.romaji {
+                out.push_str(&format!("    <p class=\"romaji\">{}</p>\n", lines(r)));
+            }
+            if let Some(s) = &q.source {
+                out.push_str(&format!(
+                    "    <p class=\"source\">— {}</p>\n",
+                    escape_html(s)
+                ));
+            }
+            out.push_str("  </figcaption>\n</figure>\n");
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+// A CSV column: its name and the value of a quote.
+type Column = (&'static str, fn(&Quote) -> Option<String>);
+
+// Same columns as a CSV pack, so the file can be used as one.
+fn csv(packs: &Packs) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let err = |e: csv::Error| format!("failed to write CSV: {e}");
+    let quotes = || packs.iter().flat_map(|(_, quotes)| quotes);
+    // these columns are only there when they are used
+    let optional_columns: [Column; 5] = [
+        ("weight", |q| q.weight.map(|w| w.to_string())),
+        ("id", |q| q.id.clone()),
+        ("season", |q| q.season.clone()),
+        ("time", |q| q.time.clone()),
+        ("months", |q| {
+            let months = q.months.as_ref()?.iter().map(u32::to_string);
+            Some(months.collect::<Vec<_>>().join(","))
+        }),
+    ];
+    let extra: Vec<_> = optional_columns
+        .iter()
+        .filter(|(_, get)| quotes().any(|q| get(q).is_some()))
+        .collect();
+    let mut header = vec!["japanese", "translation", "romaji", "source"];
+    header.extend(extra.iter().map(|(name, _)| *name));
+    writer.write_record(header).map_err(err)?;
+    for q in quotes() {
+        let optional = |s: &Option<String>| s.clone().unwrap_or_default();
+        let mut row = vec![
+            q.japanese.clone(),
+            optional(&q.translation),
+            optional(&q.romaji),
+            optional(&q.source),
+        ];
+        row.extend(extra.iter().map(|(_, get)| get(q).unwrap_or_default()));
+        writer.write_record(row).map_err(err)?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+// A list of quotes, like a JSON pack.
+fn json(packs: &Packs) -> Result<String, String> {
+    let quotes: Vec<&Quote> = packs.iter().flat_map(|(_, quotes)| quotes).collect();
+    serde_json::to_string_pretty(&quotes)
+        .map(|s| s + "\n")
+        .map_err(|e| format!("failed to write JSON: {e}"))
+}
+
+pub fn run(runtime: &RuntimeConfig, args: &ExportArgs) -> Result<(), String> {
+    let search = runtime.quote_search_path();
+    let mut packs = Vec::new();
+    for pack in args.pack.as_ref().unwrap_or(&runtime.modes) {
+        let (_, quotes) = read_pack(pack, &search)?;
+        packs.push((pack_name(pack), quotes));
+    }
+
+    let content = match args.format {
+        ExportFormat::AnkiTsv => anki_tsv(&packs),
+        ExportFormat::Markdown => markdown(&packs),
+        ExportFormat::Html => html(&packs),
+        ExportFormat::Csv => csv(&packs)?,
+        ExportFormat::Json => json(&packs)?,
+    };
+
+    match &args.output {
+        Some(path) => {
+            fs::write(path, content)
+                .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+            let count: usize = packs.iter().map(|(_, q)| q.len()).sum();
+            eprintln!("Exported {count} quotes to {}", path.display());
+        }
+        None => io::stdout()
+            .write_all(content.as_bytes())
+            .map_err(|e| format!("failed to write: {e}"))?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{format_by_name, parse_pack};
+    use crate::import::strip_html;
+
+    fn packs() -> Packs {
+        let haiku = Quote {
+            japanese: "古池や\n蛙飛び込む\n水の音".to_string(),
+            translation: Some("An old pond, a frog jumps in, \"plop\"".to_string()),
+            romaji: Some("furuike ya".to_string()),
+            source: Some("Bashō".to_string()),
+            weight: Some(2.5),
+            id: Some("furuike".to_string()),
+            season: Some("spring".to_string()),
+            time: None,
+            months: Some(vec![3, 4]),
+        };
+        let plain = Quote {
+            japanese: "猫に小判, <b>& 犬</b>".to_string(),
+            ..Default::default()
+        };
+        vec![
+            ("haiku".to_string(), vec![haiku]),
+            ("misc".to_string(), vec![plain]),
+        ]
+    }
+
+    fn reimport(format: &str, content: &str) -> Vec<Quote> {
+        let loaded = parse_pack(format_by_name(format).unwrap(), "export", content);
+        assert_eq!(loaded.errors().count(), 0, "{:?}", loaded.diagnostics);
+        loaded.quotes.into_iter().map(|(q, _)| q).collect()
+    }
+
+    fn all_quotes() -> Vec<Quote> {
+        packs().into_iter().flat_map(|(_, quotes)| quotes).collect()
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        assert_eq!(reimport("csv", &csv(&packs()).unwrap()), all_quotes());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        assert_eq!(reimport("json", &json(&packs()).unwrap()), all_quotes());
+    }
+
+    #[test]
+    fn anki_tsv_round_trip() {
+        // the fields are HTML, as `import anki` reads them back
+        let header = "japanese\ttranslation\tromaji\tsource\ttags\n";
+        let mut quotes = reimport("tsv", &format!("{header}{}", anki_tsv(&packs())));
+        let html = |s: &mut Option<String>| *s = s.as_deref().map(strip_html);
+        for q in &mut quotes {
+            q.japanese = strip_html(&q.japanese);
+            html(&mut q.translation);
+            html(&mut q.source);
+        }
+        let expected: Vec<Quote> = all_quotes()
+            .into_iter()
+            .map(|q| Quote {
+                japanese: q.japanese,
+                translation: q.translation,
+                romaji: q.romaji,
+                source: q.source,
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(quotes, expected);
+    }
+
+    #[test]
+    fn markdown_and_html_escape_the_text() {
+        let md = markdown(&packs());
+        assert!(md.contains("> 古池や  \n> 蛙飛び込む  \n> 水の音\n"));
+        assert!(md.contains("*An old pond, a frog jumps in, \"plop\"*"));
+
+        let page = html(&packs());
+        assert!(
+            page.contains("<blockquote lang=\"ja\">古池や<br>蛙飛び込む<br>水の音</blockquote>")
+        );
+        assert!(page.contains("猫に小判, &lt;b&gt;&amp; 犬&lt;/b&gt;"));
+        assert!(!page.contains("<b>&"));
+    }
+}
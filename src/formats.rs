@@ -0,0 +1,1025 @@
+// Quote pack formats.
+// Every format reads a pack as a sequence of records (a TOML table, a JSON/YAML object
+// or a CSV/TSV row), whose fields are then mapped onto `Quote` the same way.
+// TOML, CSV, TSV and lines packs are read a record at a time, so even huge packs can be
+// gone through without holding all their quotes in memory.
+use crate::context::{SEASONS, TIMES};
+use crate::diagnostic::{Diagnostic, Level, LineIndex, did_you_mean};
+use crate::quotes::{QUOTE_KEYS, Quote};
+use std::io::BufRead;
+use std::path::Path;
+use toml_edit::{Document, Item, Table};
+
+pub type Location = Option<(usize, usize)>;
+
+// Called with each record, returns false to stop reading.
+type Emit<'a> = dyn FnMut(&mut Report, Record) -> bool + 'a;
+
+pub struct PackFormat {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    read: fn(&mut dyn BufRead, &mut Report, &mut Emit),
+}
+
+// Known formats, in the order extensions are tried when a mode has none.
+pub static FORMATS: &[PackFormat] = &[
+    PackFormat {
+        name: "TOML",
+        extensions: &["toml"],
+        read: read_toml,
+    },
+    PackFormat {
+        name: "JSON",
+        extensions: &["json"],
+        read: read_json,
+    },
+    PackFormat {
+        name: "YAML",
+        extensions: &["yaml", "yml"],
+        read: read_yaml,
+    },
+    PackFormat {
+        name: "CSV",
+        extensions: &["csv"],
+        read: read_csv,
+    },
+    PackFormat {
+        name: "TSV",
+        extensions: &["tsv", "tab"],
+        read: read_tsv,
+    },
+    // japanese|translation|romaji|source, only recognized from the content
+    PackFormat {
+        name: "lines",
+        extensions: &[],
+        read: read_lines,
+    },
+];
+
+pub fn toml_format() -> &'static PackFormat {
+    &FORMATS[0]
+}
+
+// Format by name ("json") or extension ("yml").
+pub fn format_by_name(name: &str) -> Option<&'static PackFormat> {
+    let name = name.to_lowercase();
+    FORMATS
+        .iter()
+        .find(|f| f.name.to_lowercase() == name || f.extensions.contains(&name.as_str()))
+}
+
+// Format of a file from its extension, if it is a known one.
+pub fn format_of_path(path: &Path) -> Option<&'static PackFormat> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    FORMATS
+        .iter()
+        .find(|f| f.extensions.contains(&ext.as_str()))
+}
+
+// Guess the format of a pack from its content.
+pub fn sniff_format(src: &str) -> &'static PackFormat {
+    let name = |n| format_by_name(n).unwrap_or(&FORMATS[0]);
+    let trimmed = src.trim_start_matches('\u{feff}').trim_start();
+    let first_line = trimmed
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+        .unwrap_or_default();
+
+    if trimmed.starts_with('{') || (trimmed.starts_with('[') && !trimmed.starts_with("[[")) {
+        name("JSON")
+    } else if trimmed.starts_with("[[") || first_line.contains(" = ") {
+        name("TOML")
+    } else if first_line.starts_with("---")
+        || first_line.starts_with("- ")
+        || first_line == "quote:"
+    {
+        name("YAML")
+    } else if first_line.contains('|') {
+        name("lines")
+    } else if header_of(first_line, '\t') {
+        name("TSV")
+    } else if header_of(first_line, ',') {
+        name("CSV")
+    } else {
+        name("lines")
+    }
+}
+
+// Is this line a CSV/TSV header with a japanese column?
+fn header_of(line: &str, separator: char) -> bool {
+    line.contains(separator)
+        && line
+            .split(separator)
+            .any(|c| field_name(c.trim_matches('"')) == Some("japanese"))
+}
+
+// Format of a pack, from its extension or else from its content.
+pub fn detect_format(path: &Path, src: &str) -> &'static PackFormat {
+    format_of_path(path).unwrap_or_else(|| sniff_format(src))
+}
+
+// Quotes read from a pack, each with where it starts, and the problems found.
+pub struct Loaded {
+    pub quotes: Vec<(Quote, Location)>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Loaded {
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.level == Level::Error)
+    }
+}
+
+pub fn parse_pack(format: &PackFormat, file: &str, src: &str) -> Loaded {
+    let mut quotes = Vec::new();
+    let diagnostics = stream_pack(format, file, &mut src.as_bytes(), &mut |quote, location| {
+        quotes.push((quote, location));
+        true
+    });
+    Loaded {
+        quotes,
+        diagnostics,
+    }
+}
+
+// Read the quotes of a pack one at a time. `on_quote` returns false to stop early.
+// Returns the problems found on the way.
+pub fn stream_pack(
+    format: &PackFormat,
+    file: &str,
+    reader: &mut dyn BufRead,
+    on_quote: &mut dyn FnMut(Quote, Location) -> bool,
+) -> Vec<Diagnostic> {
+    let mut report = Report {
+        file: file.to_string(),
+        diagnostics: Vec::new(),
+    };
+    let mut count = 0;
+    (format.read)(reader, &mut report, &mut |report, record| {
+        count += 1;
+        let location = record.location;
+        match record_to_quote(count - 1, record, report) {
+            Some(quote) => on_quote(quote, location),
+            None => true,
+        }
+    });
+    if count == 0 && report.diagnostics.is_empty() {
+        report.warning(None, "pack has no quotes".to_string());
+    }
+    report.diagnostics
+}
+
+struct Report {
+    file: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    fn push(&mut self, location: Location, level: Level, message: String) {
+        self.diagnostics.push(Diagnostic {
+            file: self.file.clone(),
+            location,
+            level,
+            message,
+        });
+    }
+
+    fn error(&mut self, location: Location, message: String) {
+        self.push(location, Level::Error, message);
+    }
+
+    fn warning(&mut self, location: Location, message: String) {
+        self.push(location, Level::Warning, message);
+    }
+}
+
+struct Field {
+    key: String,
+    value: Option<String>,     // None if it isn't a string
+    number: Option<f64>,       // set for numbers
+    numbers: Option<Vec<f64>>, // set for lists of numbers
+    location: Location,
+}
+
+// One quote as found in a pack, before its fields are checked.
+struct Record {
+    location: Location,
+    fields: Vec<Field>,
+}
+
+// Column and key names accepted for each quote field, besides the field name itself.
+const FIELD_ALIASES: &[(&str, &[&str])] = &[
+    ("japanese", &["jp", "ja", "text", "quote"]),
+    ("translation", &["english", "en", "meaning"]),
+    ("romaji", &["reading"]),
+    ("source", &["author", "from"]),
+    ("weight", &[]),
+    ("id", &[]),
+    ("season", &[]),
+    ("time", &[]),
+    ("months", &["month"]),
+];
+
+pub fn field_name(key: &str) -> Option<&'static str> {
+    let key = key.trim().to_lowercase();
+    FIELD_ALIASES
+        .iter()
+        .find(|(name, aliases)| *name == key || aliases.contains(&key.as_str()))
+        .map(|(name, _)| *name)
+}
+
+// "unknown key 'x'", with a suggestion if it looks like a typo.
+fn unknown_field(what: &str, key: &str) -> String {
+    match did_you_mean(key, QUOTE_KEYS) {
+        Some(suggestion) => format!("unknown {what} '{key}', did you mean '{suggestion}'?"),
+        None => format!("unknown {what} '{key}'"),
+    }
+}
+
+fn record_to_quote(index: usize, record: Record, report: &mut Report) -> Option<Quote> {
+    // records without a location are named by their position instead
+    let prefix = match record.location {
+        Some(_) => String::new(),
+        None => format!("quote {index}: "),
+    };
+
+    let mut quote = Quote::default();
+    let mut japanese = None;
+    let mut valid = true;
+    for field in record.fields {
+        let location = field.location.or(record.location);
+        let Some(name) = field_name(&field.key) else {
+            report.warning(
+                location,
+                format!("{prefix}{}", unknown_field("key", &field.key)),
+            );
+            continue;
+        };
+        if name == "months" {
+            // a list, or text like "3,4" in CSV and plain text packs
+            let months = match (field.numbers, field.value.as_deref().map(str::trim)) {
+                (None, Some("")) => continue,
+                (Some(list), _) => Some(list),
+                (None, Some(text)) => text
+                    .split([',', ' '])
+                    .filter(|m| !m.is_empty())
+                    .map(|m| m.parse::<f64>().ok())
+                    .collect(),
+                (None, None) => field.number.map(|n| vec![n]),
+            };
+            match months {
+                Some(months)
+                    if !months.is_empty()
+                        && months
+                            .iter()
+                            .all(|m| m.fract() == 0.0 && (1.0..=12.0).contains(m)) =>
+                {
+                    quote.months = Some(months.into_iter().map(|m| m as u32).collect());
+                }
+                _ => {
+                    report.error(
+                        location,
+                        format!(
+                            "{prefix}'{}' must be a list of months from 1 to 12",
+                            field.key
+                        ),
+                    );
+                    valid = false;
+                }
+            }
+            continue;
+        }
+        if name == "weight" {
+            // a number, or text holding one in CSV and plain text packs
+            let weight = match (field.number, field.value.as_deref().map(str::trim)) {
+                (None, Some("")) => continue,
+                (Some(n), _) => Some(n),
+                (None, Some(text)) => text.parse::<f64>().ok(),
+                (None, None) => None,
+            };
+            match weight {
+                Some(w) if w.is_finite() && w > 0.0 => quote.weight = Some(w),
+                _ => {
+                    report.error(
+                        location,
+                        format!("{prefix}'{}' must be a positive number", field.key),
+                    );
+                    valid = false;
+                }
+            }
+            continue;
+        }
+        let Some(value) = field.value else {
+            report.error(
+                location,
+                format!("{prefix}'{}' must be a string", field.key),
+            );
+            valid = false;
+            continue;
+        };
+        match name {
+            "japanese" => japanese = Some(value),
+            // empty cells are missing values
+            _ if value.is_empty() => {}
+            "translation" => quote.translation = Some(value),
+            "romaji" => quote.romaji = Some(value),
+            "source" => quote.source = Some(value),
+            "season" | "time" => {
+                let allowed = if name == "season" { SEASONS } else { TIMES };
+                let value = value.trim().to_lowercase();
+                // "fall" is autumn too
+                let value = if value == "fall" {
+                    "autumn".to_string()
+                } else {
+                    value
+                };
+                if allowed.contains(&value.as_str()) {
+                    match name {
+                        "season" => quote.season = Some(value),
+                        _ => quote.time = Some(value),
+                    }
+                } else {
+                    report.error(
+                        location,
+                        format!(
+                            "{prefix}'{}' must be one of {}",
+                            field.key,
+                            allowed.join(", ")
+                        ),
+                    );
+                    valid = false;
+                }
+            }
+            // IDs are written in pack:id@seed replay tokens
+            _ if value.contains(|c: char| c == ':' || c == '@' || c.is_whitespace()) => {
+                report.error(
+                    location,
+                    format!("{prefix}'{}' can't contain ':', '@' or spaces", field.key),
+                );
+                valid = false;
+            }
+            _ => quote.id = Some(value),
+        }
+    }
+
+    if !valid {
+        return None;
+    }
+    let Some(japanese) = japanese else {
+        report.error(record.location, format!("{prefix}missing 'japanese'"));
+        return None;
+    };
+    quote.japanese = japanese;
+    Some(quote)
+}
+
+fn read_all(reader: &mut dyn BufRead, report: &mut Report) -> Option<String> {
+    let mut src = String::new();
+    match reader.read_to_string(&mut src) {
+        Ok(_) => Some(src),
+        Err(e) => {
+            report.error(None, format!("failed to read: {e}"));
+            None
+        }
+    }
+}
+
+// TOML packs are split before each [[quote]] header and the pieces parsed one by one.
+fn read_toml(reader: &mut dyn BufRead, report: &mut Report, emit: &mut Emit) {
+    let mut chunk = String::new();
+    let mut chunk_line = 1;
+    let mut line_no = 0;
+    let mut line = String::new();
+    // the closing delimiter of a multi-line string we are in
+    let mut in_string: Option<&str> = None;
+    loop {
+        line.clear();
+        let read = match reader.read_line(&mut line) {
+            Ok(read) => read,
+            Err(e) => {
+                report.error(None, format!("failed to read: {e}"));
+                return;
+            }
+        };
+        let header = in_string.is_none() && line.trim_start().starts_with("[[quote]]");
+        if (read == 0 || header) && !chunk.trim().is_empty() {
+            if !read_toml_chunk(&chunk, chunk_line, report, emit) {
+                return;
+            }
+            chunk.clear();
+            chunk_line = line_no + 1;
+        }
+        if read == 0 {
+            return;
+        }
+        line_no += 1;
+        if chunk.is_empty() {
+            chunk_line = line_no;
+        }
+        for delimiter in ["\"\"\"", "'''"] {
+            if in_string.is_none_or(|d| d == delimiter) && line.matches(delimiter).count() % 2 == 1
+            {
+                in_string = match in_string {
+                    Some(_) => None,
+                    None => Some(delimiter),
+                };
+            }
+        }
+        chunk.push_str(&line);
+    }
+}
+
+// Parse a piece of a TOML pack starting at line `first_line`.
+fn read_toml_chunk(src: &str, first_line: usize, report: &mut Report, emit: &mut Emit) -> bool {
+    let lines = LineIndex::new(src);
+    let location = |offset: usize| {
+        let (line, col) = lines.line_col(offset);
+        (line + first_line - 1, col)
+    };
+    let doc = match Document::parse(src) {
+        Ok(doc) => doc,
+        Err(e) => {
+            report.error(
+                e.span().map(|s| location(s.start)),
+                e.message().trim().to_string(),
+            );
+            return true;
+        }
+    };
+    let span_location = |span: Option<std::ops::Range<usize>>| span.map(|s| location(s.start));
+    let record = |table: &Table| Record {
+        location: span_location(
+            table
+                .key("japanese")
+                .and_then(|k| k.span())
+                .or_else(|| table.span()),
+        ),
+        fields: table
+            .iter()
+            .map(|(key, item)| Field {
+                key: key.to_string(),
+                value: item.as_str().map(str::to_string),
+                number: item
+                    .as_float()
+                    .or_else(|| item.as_integer().map(|i| i as f64)),
+                numbers: item.as_array().and_then(|list| {
+                    list.iter()
+                        .map(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64)))
+                        .collect()
+                }),
+                location: span_location(table.key(key).and_then(|k| k.span())),
+            })
+            .collect(),
+    };
+
+    // a single quote, without [[quote]]
+    if !doc.contains_key("quote") && doc.contains_key("japanese") {
+        return emit(report, record(doc.as_table()));
+    }
+
+    for (key, _) in doc.iter() {
+        if key != "quote" {
+            let location = span_location(doc.key(key).and_then(|k| k.span()));
+            report.warning(location, format!("unknown key '{key}'"));
+        }
+    }
+    match doc.get("quote") {
+        None => true,
+        Some(Item::ArrayOfTables(tables)) => tables.iter().all(|t| emit(report, record(t))),
+        Some(item) => {
+            report.error(
+                span_location(item.span()),
+                "'quote' must be an array of tables ([[quote]])".to_string(),
+            );
+            true
+        }
+    }
+}
+
+// Parser messages end with the position, which is already part of the diagnostic.
+fn without_position(message: String) -> String {
+    match message.find(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
+    }
+}
+
+// JSON and YAML packs are either a list of quotes or an object with a "quote" list,
+// like the TOML layout, or a single quote.
+fn read_json(reader: &mut dyn BufRead, report: &mut Report, emit: &mut Emit) {
+    use serde_json::Value;
+    let Some(src) = read_all(reader, report) else {
+        return;
+    };
+    let value: Value = match serde_json::from_str(&src) {
+        Ok(value) => value,
+        Err(e) => {
+            report.error(
+                Some((e.line(), e.column())),
+                without_position(e.to_string()),
+            );
+            return;
+        }
+    };
+    let items = match value {
+        Value::Array(items) => items,
+        Value::Object(map) if map.contains_key("japanese") => vec![Value::Object(map)],
+        Value::Object(mut map) => match map.remove("quote") {
+            Some(Value::Array(items)) => items,
+            _ => {
+                report.error(
+                    None,
+                    "expected a list of quotes or a \"quote\" list".to_string(),
+                );
+                return;
+            }
+        },
+        _ => {
+            report.error(None, "expected a list of quotes".to_string());
+            return;
+        }
+    };
+
+    for (i, item) in items.into_iter().enumerate() {
+        let Value::Object(map) = item else {
+            report.error(None, format!("quote {i}: expected an object"));
+            continue;
+        };
+        let record = Record {
+            location: None,
+            fields: map
+                .into_iter()
+                .map(|(key, value)| Field {
+                    key,
+                    value: value.as_str().map(str::to_string),
+                    number: value.as_f64(),
+                    numbers: value
+                        .as_array()
+                        .and_then(|list| list.iter().map(Value::as_f64).collect()),
+                    location: None,
+                })
+                .collect(),
+        };
+        if !emit(report, record) {
+            return;
+        }
+    }
+}
+
+fn read_yaml(reader: &mut dyn BufRead, report: &mut Report, emit: &mut Emit) {
+    use serde_yaml_ng::Value;
+    let Some(src) = read_all(reader, report) else {
+        return;
+    };
+    let value: Value = match serde_yaml_ng::from_str(&src) {
+        Ok(value) => value,
+        Err(e) => {
+            let location = e.location().map(|l| (l.line(), l.column()));
+            report.error(location, without_position(e.to_string()));
+            return;
+        }
+    };
+    let items = match value {
+        Value::Sequence(items) => items,
+        Value::Mapping(map) if map.contains_key("japanese") => vec![Value::Mapping(map)],
+        Value::Mapping(mut map) => match map.remove("quote") {
+            Some(Value::Sequence(items)) => items,
+            _ => {
+                report.error(
+                    None,
+                    "expected a list of quotes or a 'quote' list".to_string(),
+                );
+                return;
+            }
+        },
+        Value::Null => return,
+        _ => {
+            report.error(None, "expected a list of quotes".to_string());
+            return;
+        }
+    };
+
+    for (i, item) in items.into_iter().enumerate() {
+        let Value::Mapping(map) = item else {
+            report.error(None, format!("quote {i}: expected a mapping"));
+            continue;
+        };
+        let record = Record {
+            location: None,
+            fields: map
+                .into_iter()
+                .map(|(key, value)| Field {
+                    key: key.as_str().unwrap_or_default().to_string(),
+                    value: value.as_str().map(str::to_string),
+                    number: value.as_f64(),
+                    numbers: value
+                        .as_sequence()
+                        .and_then(|list| list.iter().map(Value::as_f64).collect()),
+                    location: None,
+                })
+                .collect(),
+        };
+        if !emit(report, record) {
+            return;
+        }
+    }
+}
+
+fn read_csv(reader: &mut dyn BufRead, report: &mut Report, emit: &mut Emit) {
+    read_delimited(reader, b',', true, report, emit)
+}
+
+// Tab separated values usually don't quote fields, so quote characters are kept as is.
+fn read_tsv(reader: &mut dyn BufRead, report: &mut Report, emit: &mut Emit) {
+    read_delimited(reader, b'\t', false, report, emit)
+}
+
+// Skip a UTF-8 byte order mark, as left by some spreadsheet programs.
+fn skip_bom(reader: &mut dyn BufRead) {
+    if reader
+        .fill_buf()
+        .is_ok_and(|buf| buf.starts_with("\u{feff}".as_bytes()))
+    {
+        reader.consume(3);
+    }
+}
+
+// One quote per line, its fields separated by | (or by tabs in a line without any). Empty
+// fields are skipped and "\n" starts a new line, like "古池や\n蛙飛び込む\n水の音|An old pond...".
+fn read_lines(reader: &mut dyn BufRead, report: &mut Report, emit: &mut Emit) {
+    let names = ["japanese", "translation", "romaji", "source"];
+    skip_bom(reader);
+    for (i, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                report.error(Some((i + 1, 1)), format!("failed to read: {e}"));
+                return;
+            }
+        };
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let separator = if line.contains('|') { '|' } else { '\t' };
+        let record = Record {
+            location: Some((i + 1, 1)),
+            fields: names
+                .iter()
+                .zip(line.splitn(names.len(), separator))
+                .map(|(name, value)| Field {
+                    key: name.to_string(),
+                    value: Some(value.trim().replace("\\n", "\n")),
+                    number: None,
+                    numbers: None,
+                    location: None,
+                })
+                .collect(),
+        };
+        if !emit(report, record) {
+            return;
+        }
+    }
+}
+
+// The first row names the columns. Lines starting with # are comments.
+fn read_delimited(
+    reader: &mut dyn BufRead,
+    delimiter: u8,
+    quoting: bool,
+    report: &mut Report,
+    emit: &mut Emit,
+) {
+    skip_bom(reader);
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quoting(quoting)
+        .comment(Some(b'#'))
+        .flexible(true)
+        .from_reader(reader);
+    let position = |p: Option<&csv::Position>| p.map(|p| (p.line() as usize, 1));
+
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => {
+            report.error(position(e.position()), e.to_string());
+            return;
+        }
+    };
+    // unknown columns are reported once, not on every row
+    let header_line = position(headers.position());
+    let mut columns = Vec::new();
+    for (i, header) in headers.iter().enumerate() {
+        if field_name(header).is_some() {
+            columns.push((i, header.to_string()));
+        } else if !header.trim().is_empty() {
+            report.warning(header_line, unknown_field("column", header));
+        }
+    }
+    if !columns
+        .iter()
+        .any(|(_, h)| field_name(h) == Some("japanese"))
+    {
+        report.error(
+            header_line,
+            "no 'japanese' column in the header row".to_string(),
+        );
+        return;
+    }
+
+    let mut row = csv::StringRecord::new();
+    loop {
+        match reader.read_record(&mut row) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                report.error(position(e.position()), e.to_string());
+                // a failed read won't get any better
+                if matches!(e.kind(), csv::ErrorKind::Io(_)) {
+                    return;
+                }
+                continue;
+            }
+        }
+        if row.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+        let location = position(row.position());
+        if row.len() > headers.len() {
+            report.warning(
+                location,
+                format!(
+                    "row has {} fields, the header has {}",
+                    row.len(),
+                    headers.len()
+                ),
+            );
+        }
+        let record = Record {
+            location,
+            fields: columns
+                .iter()
+                .filter_map(|(i, header)| {
+                    row.get(*i).map(|cell| Field {
+                        key: header.clone(),
+                        value: Some(cell.trim().to_string()),
+                        number: None,
+                        numbers: None,
+                        location,
+                    })
+                })
+                .collect(),
+        };
+        if !emit(report, record) {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(src: &str, stop_after: usize) -> (Vec<(String, Location)>, Vec<Diagnostic>) {
+        let mut quotes = Vec::new();
+        let diagnostics = stream_pack(
+            toml_format(),
+            "pack.toml",
+            &mut src.as_bytes(),
+            &mut |quote, location| {
+                quotes.push((quote.japanese, location));
+                quotes.len() < stop_after
+            },
+        );
+        (quotes, diagnostics)
+    }
+
+    #[test]
+    fn toml_chunks_keep_their_lines() {
+        let src = "\
+[[quote]]
+japanese = \"一\"
+
+[[quote]]
+japanese = \"\"\"
+[[quote]] inside a string
+\"\"\"
+
+[[quote]]
+japanese = \"三\"
+translation = oops
+
+[[quote]]
+japanese = '''四'''
+";
+        let (quotes, diagnostics) = stream(src, usize::MAX);
+        let found: Vec<(&str, Location)> = quotes
+            .iter()
+            .map(|(text, location)| (text.as_str(), *location))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("一", Some((2, 1))),
+                ("[[quote]] inside a string\n", Some((5, 1))),
+                ("四", Some((14, 1))),
+            ]
+        );
+        // the error is reported at its line in the file, not in its chunk
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].level, Level::Error);
+        assert_eq!(diagnostics[0].location.map(|(line, _)| line), Some(11));
+    }
+
+    #[test]
+    fn toml_stream_stops_early() {
+        let src = "[[quote]]\njapanese = \"一\"\n[[quote]]\njapanese = \"二\"\n";
+        let (quotes, diagnostics) = stream(src, 1);
+        assert_eq!(quotes.len(), 1);
+        assert!(diagnostics.is_empty());
+        assert_eq!(stream(src, usize::MAX).0.len(), 2);
+    }
+
+    // Quotes as (japanese, translation, weight), and the problems as (level, line, message).
+    type Parsed = (
+        Vec<(String, Option<String>, Option<f64>)>,
+        Vec<(Level, Option<usize>, String)>,
+    );
+
+    fn parse(format: &str, src: &str) -> Parsed {
+        let loaded = parse_pack(format_by_name(format).unwrap(), "pack", src);
+        let quotes = loaded
+            .quotes
+            .into_iter()
+            .map(|(q, _)| (q.japanese, q.translation, q.weight))
+            .collect();
+        let diagnostics = loaded
+            .diagnostics
+            .into_iter()
+            .map(|d| (d.level, d.location.map(|(line, _)| line), d.message))
+            .collect();
+        (quotes, diagnostics)
+    }
+
+    fn quote(
+        japanese: &str,
+        translation: &str,
+        weight: Option<f64>,
+    ) -> (String, Option<String>, Option<f64>) {
+        (japanese.to_string(), Some(translation.to_string()), weight)
+    }
+
+    #[test]
+    fn csv_reads_aliased_columns() {
+        let src = "JP,English,weight,notes\n猫,cat,2,x\n\n\"犬, 大\",\"dog, big\",,\n";
+        let (quotes, diagnostics) = parse("csv", src);
+        assert_eq!(
+            quotes,
+            [
+                quote("猫", "cat", Some(2.0)),
+                quote("犬, 大", "dog, big", None)
+            ]
+        );
+        assert_eq!(
+            diagnostics,
+            [(
+                Level::Warning,
+                Some(1),
+                "unknown column 'notes'".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn tsv_keeps_quote_characters() {
+        let (quotes, diagnostics) = parse("tsv", "text\tmeaning\n「猫」\t\"cat\"\n");
+        assert_eq!(quotes, [quote("「猫」", "\"cat\"", None)]);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn csv_without_japanese_column() {
+        let (quotes, diagnostics) = parse("csv", "english,romaji\ncat,neko\n");
+        assert!(quotes.is_empty());
+        assert_eq!(
+            diagnostics,
+            [(
+                Level::Error,
+                Some(1),
+                "no 'japanese' column in the header row".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn csv_bad_weight_drops_its_row() {
+        let (quotes, diagnostics) = parse("csv", "japanese,weight\n猫,heavy\n犬,0\n鳥,1.5\n");
+        assert_eq!(quotes, [("鳥".to_string(), None, Some(1.5))]);
+        let lines: Vec<_> = diagnostics.iter().map(|d| (d.0, d.1)).collect();
+        assert_eq!(lines, [(Level::Error, Some(2)), (Level::Error, Some(3))]);
+        assert_eq!(diagnostics[0].2, "'weight' must be a positive number");
+    }
+
+    #[test]
+    fn json_reads_aliased_fields() {
+        let src = r#"{"quote": [{"jp": "猫", "meaning": "cat", "weight": 2}, {"text": "犬", "en": "dog"}]}"#;
+        let (quotes, diagnostics) = parse("json", src);
+        assert_eq!(
+            quotes,
+            [quote("猫", "cat", Some(2.0)), quote("犬", "dog", None)]
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn json_problems_name_the_quote() {
+        let src = r#"[{"english": "cat"}, {"japanese": "犬", "weight": -1}, {"japanese": "鳥"}]"#;
+        let (quotes, diagnostics) = parse("json", src);
+        assert_eq!(quotes, [("鳥".to_string(), None, None)]);
+        let messages: Vec<_> = diagnostics.iter().map(|d| (d.0, d.2.as_str())).collect();
+        assert_eq!(
+            messages,
+            [
+                (Level::Error, "quote 0: missing 'japanese'"),
+                (Level::Error, "quote 1: 'weight' must be a positive number"),
+            ]
+        );
+
+        let (_, diagnostics) = parse("json", "[{\"japanese\": \"猫\",}]");
+        assert_eq!(diagnostics[0].0, Level::Error);
+        assert_eq!(diagnostics[0].1, Some(1));
+    }
+
+    #[test]
+    fn yaml_reads_aliased_fields() {
+        let src = "- ja: 猫\n  english: cat\n  weight: 3\n- quote: 犬\n  en: dog\n";
+        let (quotes, diagnostics) = parse("yaml", src);
+        assert_eq!(
+            quotes,
+            [quote("猫", "cat", Some(3.0)), quote("犬", "dog", None)]
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn yaml_problems_name_the_quote() {
+        let src = "quote:\n  - english: cat\n  - japanese: 犬\n    weight: lots\n";
+        let (quotes, diagnostics) = parse("yaml", src);
+        assert!(quotes.is_empty());
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.2.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "quote 0: missing 'japanese'",
+                "quote 1: 'weight' must be a positive number"
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_skip_blank_lines_and_comments() {
+        let src =
+            "\n猫に小判|Pearls before swine\n   \n# a comment\n古池や\\n蛙飛び込む||furuike ya\n";
+        let (quotes, diagnostics) = parse("lines", src);
+        assert_eq!(
+            quotes,
+            [
+                quote("猫に小判", "Pearls before swine", None),
+                ("古池や\n蛙飛び込む".to_string(), None, None),
+            ]
+        );
+        assert!(diagnostics.is_empty());
+        let loaded = parse_pack(format_by_name("lines").unwrap(), "pack", src);
+        assert_eq!(loaded.quotes[1].0.romaji.as_deref(), Some("furuike ya"));
+        assert_eq!(loaded.quotes[1].1, Some((5, 1)));
+    }
+
+    #[test]
+    fn lines_take_a_tab_separated_translation() {
+        let (quotes, _) = parse("lines", "猫\tcat\n犬 | dog\t(big)\n");
+        assert_eq!(
+            quotes,
+            [quote("猫", "cat", None), quote("犬", "dog\t(big)", None)]
+        );
+        // a tab separated line without a header is read as lines, not TSV
+        assert_eq!(sniff_format("猫\tcat\n").name, "lines");
+    }
+
+    #[test]
+    fn lines_read_crlf() {
+        let (quotes, diagnostics) = parse("lines", "\u{feff}猫|cat\r\n\r\n犬\tdog\r\n");
+        assert_eq!(quotes, [quote("猫", "cat", None), quote("犬", "dog", None)]);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn format_is_sniffed_from_the_content() {
+        let name = |src| sniff_format(src).name;
+        assert_eq!(name("[[quote]]\njapanese = \"猫\""), "TOML");
+        assert_eq!(name("[{\"japanese\": \"猫\"}]"), "JSON");
+        assert_eq!(name("- japanese: 猫"), "YAML");
+        assert_eq!(name("jp,english\n猫,cat"), "CSV");
+        assert_eq!(name("jp\tenglish\n猫\tcat"), "TSV");
+        assert_eq!(name("猫|cat"), "lines");
+    }
+}
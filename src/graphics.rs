@@ -0,0 +1,253 @@
+// The boxed quote as a picture: an SVG image or an HTML page. Both lay the box out on
+// the same grid of columns and lines as the terminal, with the colors of the config
+// and fonts from the system, so nothing is fetched and the output depends only on the
+// quote and the settings.
+use crate::config::RuntimeConfig;
+use crate::display::{Layout, Part, color_to_hex};
+use crate::export::escape_html;
+use crate::output::part_color;
+
+// Japanese fonts in order of preference, the generic family last.
+const FONTS: &str = "'Noto Sans Mono CJK JP', 'Noto Sans CJK JP', 'Source Han Sans', \
+                     'Hiragino Sans', 'Yu Gothic', 'Meiryo', monospace";
+
+const BACKGROUND: &str = "#1e1e1e";
+const FONT_SIZE: f64 = 16.0;
+// A column is as wide as a Latin letter of a monospace font, Japanese takes two
+const COLUMN: f64 = FONT_SIZE * 0.6;
+const LINE: f64 = FONT_SIZE * 1.5;
+const MARGIN: f64 = FONT_SIZE;
+
+// What both pictures need: the rows of the box and how it looks.
+struct Picture {
+    rows: Vec<Option<(Part, String)>>,
+    inner_width: usize,
+    columns: usize,
+    border: Option<String>, // color of the border, None without one
+    rounded: bool,
+    padding: usize,
+    centered: bool,
+    bold: bool,
+}
+
+impl Picture {
+    fn new(
+        runtime: &RuntimeConfig,
+        layout: &Layout,
+        max_width: Option<usize>,
+        max_lines: Option<usize>,
+    ) -> Self {
+        let inner_width = layout.inner_width(max_width);
+        let border = runtime
+            .border
+            .then(|| color_to_hex(&runtime.border_color).unwrap_or("#ffffff".to_string()));
+        Picture {
+            rows: layout.cut_rows(inner_width, max_lines),
+            inner_width,
+            columns: inner_width + layout.sides(),
+            border,
+            rounded: runtime.rounded_border,
+            padding: runtime.horizontal_padding,
+            centered: runtime.centered,
+            bold: runtime.bold,
+        }
+    }
+
+    // The text of a row as it is drawn: centered rows are centered again by the
+    // picture, the others keep their indentation.
+    fn text<'a>(&self, content: &'a str) -> &'a str {
+        if self.centered {
+            content.trim()
+        } else {
+            content.trim_end()
+        }
+    }
+}
+
+pub fn svg(
+    runtime: &RuntimeConfig,
+    layout: &Layout,
+    max_width: Option<usize>,
+    max_lines: Option<usize>,
+) -> String {
+    let picture = Picture::new(runtime, layout, max_width, max_lines);
+    let border_lines = if picture.border.is_some() { 2 } else { 0 };
+    let box_width = picture.columns as f64 * COLUMN;
+    let box_height = (picture.rows.len() + border_lines) as f64 * LINE;
+    let width = box_width + 2.0 * MARGIN;
+    let height = box_height + 2.0 * MARGIN;
+
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width:.1}\" height=\"{height:.1}\" \
+         viewBox=\"0 0 {width:.1} {height:.1}\">\n\
+         <rect width=\"100%\" height=\"100%\" fill=\"{BACKGROUND}\"/>\n"
+    );
+    // the border runs through the middle of the first and last lines and columns,
+    // where the terminal draws it
+    if let Some(color) = &picture.border {
+        let radius = if picture.rounded { COLUMN } else { 0.0 };
+        out.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"{radius:.1}\" \
+             fill=\"none\" stroke=\"{color}\" stroke-width=\"1.5\"/>\n",
+            MARGIN + COLUMN / 2.0,
+            MARGIN + LINE / 2.0,
+            box_width - COLUMN,
+            box_height - LINE,
+        ));
+    }
+
+    let (anchor, x) = if picture.centered {
+        ("middle", MARGIN + box_width / 2.0)
+    } else {
+        let sides = picture.padding + if picture.border.is_some() { 1 } else { 0 };
+        ("start", MARGIN + sides as f64 * COLUMN)
+    };
+    out.push_str(&format!(
+        "<g font-family=\"{FONTS}\" font-size=\"{FONT_SIZE:.1}\" text-anchor=\"{anchor}\" \
+         dominant-baseline=\"central\" xml:space=\"preserve\">\n"
+    ));
+    let first = border_lines / 2;
+    for (i, row) in picture.rows.iter().enumerate() {
+        let Some((part, content)) = row else {
+            continue;
+        };
+        let y = MARGIN + ((first + i) as f64 + 0.5) * LINE;
+        out.push_str(&format!(
+            "<text x=\"{x:.1}\" y=\"{y:.1}\"{}>{}</text>\n",
+            attributes(runtime, &picture, *part),
+            escape_html(picture.text(content))
+        ));
+    }
+    out.push_str("</g>\n</svg>\n");
+    out
+}
+
+// Color and weight of the text of a part.
+fn attributes(runtime: &RuntimeConfig, picture: &Picture, part: Part) -> String {
+    let mut attributes = String::new();
+    if let Some(color) = part_color(runtime, part) {
+        attributes.push_str(&format!(" fill=\"{color}\""));
+    }
+    if part == Part::Japanese && picture.bold {
+        attributes.push_str(" font-weight=\"bold\"");
+    }
+    attributes
+}
+
+pub fn html(
+    runtime: &RuntimeConfig,
+    layout: &Layout,
+    max_width: Option<usize>,
+    max_lines: Option<usize>,
+) -> String {
+    let picture = Picture::new(runtime, layout, max_width, max_lines);
+    // the border runs through the middle of a line and a column, half of each is
+    // left inside
+    let border = match &picture.border {
+        Some(color) => {
+            let radius = if picture.rounded { COLUMN } else { 0.0 };
+            format!(
+                "padding: {:.1}px {}.5ch; border: 1.5px solid {color}; border-radius: {radius:.1}px;",
+                LINE / 2.0,
+                picture.padding
+            )
+        }
+        None => format!("padding: 0 {}ch;", picture.padding),
+    };
+    let color = |part| part_color(runtime, part).unwrap_or("inherit".to_string());
+    let weight = if picture.bold { "bold" } else { "normal" };
+    let align = if picture.centered { "center" } else { "left" };
+
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"ja\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>kotofetch</title>\n<style>\n\
+         body {{ margin: 0; min-height: 100vh; display: flex; align-items: center; \
+         justify-content: center; background: {BACKGROUND}; }}\n\
+         .kotofetch {{ font-family: {FONTS}; font-size: {FONT_SIZE:.0}px; line-height: 1.5; \
+         white-space: pre; text-align: {align}; width: {}ch; {border} }}\n\
+         .japanese {{ color: {}; font-weight: {weight}; }}\n\
+         .translation {{ color: {}; }}\n\
+         .source {{ color: {}; }}\n\
+         </style>\n</head>\n<body>\n<div class=\"kotofetch\">\n",
+        picture.inner_width,
+        color(Part::Japanese),
+        color(Part::Translation),
+        color(Part::Source),
+    );
+    for row in &picture.rows {
+        match row {
+            Some((part, content)) => {
+                let class = match part {
+                    Part::Japanese => "japanese",
+                    Part::Translation => "translation",
+                    Part::Separator | Part::Source => "source",
+                };
+                out.push_str(&format!(
+                    "<div class=\"{class}\">{}</div>\n",
+                    escape_html(picture.text(content))
+                ));
+            }
+            None => out.push_str("<div> </div>\n"),
+        }
+    }
+    out.push_str("</div>\n</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quotes::Quote;
+
+    fn runtime() -> RuntimeConfig {
+        RuntimeConfig {
+            font_size: "small".to_string(),
+            ..RuntimeConfig::default()
+        }
+    }
+
+    #[test]
+    fn svg_escapes_the_quote() {
+        let runtime = runtime();
+        let quote = Quote {
+            japanese: "a<b & \"c\"".to_string(),
+            translation: Some("'x' > y".to_string()),
+            ..Quote::default()
+        };
+        let svg = svg(&runtime, &Layout::new(&runtime, &quote), None, None);
+        let texts: Vec<_> = svg.lines().filter(|l| l.starts_with("<text")).collect();
+        assert_eq!(
+            texts,
+            [
+                "<text x=\"97.6\" y=\"76.0\" fill=\"#e5e5e5\" font-weight=\"bold\">\
+                 a&lt;b &amp; &quot;c&quot;</text>",
+                "<text x=\"97.6\" y=\"124.0\" fill=\"#808080\">'x' &gt; y</text>",
+            ]
+        );
+    }
+
+    #[test]
+    fn pictures_keep_to_max_lines() {
+        let runtime = runtime();
+        let quote = Quote {
+            japanese: "一行目の言葉はとても長いのでいくつかの行に分かれて続いていく".to_string(),
+            translation: Some("a translation long enough to take lines of its own".to_string()),
+            ..Quote::default()
+        };
+        let layout = Layout::new(&runtime, &quote);
+        let borders = if runtime.border { 2 } else { 0 };
+        let lines = layout.boxed(Some(20), None, Some(4)).len();
+        assert_eq!(lines, 4);
+
+        let svg = svg(&runtime, &layout, Some(20), Some(4));
+        let texts = svg.lines().filter(|l| l.starts_with("<text")).count();
+        assert!(texts <= 4 - borders, "{svg}");
+        assert!(svg.contains('…'), "{svg}");
+
+        let html = html(&runtime, &layout, Some(20), Some(4));
+        // every row is a div inside the one of the box
+        let rows = html.lines().filter(|l| l.starts_with("<div")).count() - 1;
+        assert_eq!(rows, 4 - borders, "{html}");
+        assert!(html.contains('…'), "{html}");
+    }
+}
@@ -0,0 +1,469 @@
+// Import flashcards from other programs as quote packs.
+use crate::cli::{AnkiArgs, ImportFrom};
+use crate::manage::{quote_table, write_document};
+use crate::quotes::{Quote, pack_file_name, pack_name, user_quotes_dir};
+use crate::romaji::kana_to_romaji;
+use crate::state::scratch_file;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use toml_edit::{ArrayOfTables, DocumentMut, Item};
+
+// A field given by name or by its 1-based position.
+enum FieldRef {
+    Name(String),
+    Index(usize),
+}
+
+impl FieldRef {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s.parse::<usize>() {
+            Ok(0) => Err("field numbers start at 1".to_string()),
+            Ok(n) => Ok(FieldRef::Index(n - 1)),
+            Err(_) => Ok(FieldRef::Name(s.to_string())),
+        }
+    }
+
+    // Position of the field for a note type with these field names.
+    fn resolve(&self, names: &[String]) -> Option<usize> {
+        match self {
+            FieldRef::Index(i) => Some(*i),
+            FieldRef::Name(name) => names.iter().position(|n| n.eq_ignore_ascii_case(name)),
+        }
+    }
+}
+
+// Notes as lists of fields, with the field names of each note type.
+#[derive(Default)]
+struct Notes {
+    field_names: HashMap<i64, Vec<String>>,
+    notes: Vec<(i64, Vec<String>)>,
+}
+
+// Read notes from an Anki collection database.
+fn read_collection(path: &Path) -> Result<Notes, String> {
+    let err = |e: rusqlite::Error| format!("failed to read the Anki collection: {e}");
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(err)?;
+    let mut notes = Notes::default();
+
+    // newer collections have a fields table, older ones keep note types as JSON in col.models
+    let has_fields_table: bool = conn
+        .query_row(
+            "SELECT count(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'fields'",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(err)?;
+    if has_fields_table {
+        let mut stmt = conn
+            .prepare("SELECT ntid, name FROM fields ORDER BY ntid, ord")
+            .map_err(err)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(err)?;
+        for row in rows {
+            let (ntid, name) = row.map_err(err)?;
+            notes.field_names.entry(ntid).or_default().push(name);
+        }
+    } else {
+        let models: String = conn
+            .query_row("SELECT models FROM col", [], |row| row.get(0))
+            .map_err(err)?;
+        let models: serde_json::Value = serde_json::from_str(&models)
+            .map_err(|e| format!("failed to read the Anki note types: {e}"))?;
+        for (id, model) in models.as_object().into_iter().flatten() {
+            let mut fields: Vec<(i64, String)> = model["flds"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|f| {
+                    let ord = f["ord"].as_i64().unwrap_or_default();
+                    (ord, f["name"].as_str().unwrap_or_default().to_string())
+                })
+                .collect();
+            fields.sort();
+            let names = fields.into_iter().map(|(_, name)| name).collect();
+            notes
+                .field_names
+                .insert(id.parse().unwrap_or_default(), names);
+        }
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT mid, flds FROM notes ORDER BY id")
+        .map_err(err)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(err)?;
+    for row in rows {
+        let (mid, flds) = row.map_err(err)?;
+        let fields = flds.split('\u{1f}').map(str::to_string).collect();
+        notes.notes.push((mid, fields));
+    }
+    Ok(notes)
+}
+
+// Read notes from an .apkg or .colpkg file, a zip archive holding the collection database.
+fn read_package(path: &Path) -> Result<Notes, String> {
+    let file = File::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| format!("{} is not an Anki package: {e}", path.display()))?;
+
+    // collection.anki21b is zstd compressed, the others are written by older Anki versions
+    let mut database = None;
+    for name in [
+        "collection.anki21b",
+        "collection.anki21",
+        "collection.anki2",
+    ] {
+        if let Ok(mut entry) = archive.by_name(name) {
+            let mut bytes = Vec::new();
+            entry
+                .read_to_end(&mut bytes)
+                .map_err(|e| format!("failed to extract {name}: {e}"))?;
+            if name.ends_with('b') {
+                bytes = zstd::decode_all(bytes.as_slice())
+                    .map_err(|e| format!("failed to decompress {name}: {e}"))?;
+            }
+            database = Some(bytes);
+            break;
+        }
+    }
+    let database = database.ok_or_else(|| format!("no collection found in {}", path.display()))?;
+
+    let (tmp, mut file) = scratch_file("import", "anki2")?;
+    if let Err(e) = file.write_all(&database) {
+        fs::remove_file(&tmp).ok();
+        return Err(format!("failed to write {}: {e}", tmp.display()));
+    }
+    drop(file);
+    let notes = read_collection(&tmp);
+    fs::remove_file(&tmp).ok();
+    notes
+}
+
+// Read notes from a "Notes in Plain Text" export.
+// Its header lines (#separator:tab, #columns:Front\tBack, ...) are used when present.
+fn read_text(path: &Path) -> Result<Notes, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+
+    let mut separator = b'\t';
+    let mut columns = Vec::new();
+    for line in content.lines().take_while(|l| l.starts_with('#')) {
+        let Some((key, value)) = line[1..].split_once(':') else {
+            continue;
+        };
+        match key.trim().to_lowercase().as_str() {
+            "separator" => {
+                separator = match value.trim().to_lowercase().as_str() {
+                    "comma" | "," => b',',
+                    "semicolon" | ";" => b';',
+                    "space" | " " => b' ',
+                    "pipe" | "|" => b'|',
+                    "colon" | ":" => b':',
+                    _ => b'\t',
+                }
+            }
+            "columns" => columns = value.split(separator as char).map(str::to_string).collect(),
+            _ => {}
+        }
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(separator)
+        .comment(Some(b'#'))
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let mut notes = Notes::default();
+    notes.field_names.insert(0, columns);
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        notes
+            .notes
+            .push((0, record.iter().map(str::to_string).collect()));
+    }
+    Ok(notes)
+}
+
+// Remove HTML tags and decode the common entities. Line breaks are kept.
+pub fn strip_html(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            out.push_str(&rest[start..]);
+            rest = "";
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim().to_lowercase();
+        if tag.starts_with("br") || tag == "/div" || tag == "/p" || tag == "/li" {
+            out.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+
+    let mut decoded = String::new();
+    let mut rest = out.as_str();
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let replacement = entity.and_then(|(name, _)| match name {
+            "nbsp" => Some(' '),
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = name.strip_prefix('#')?;
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (replacement, entity) {
+            (Some(c), Some((_, end))) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Anki furigana, like "日本[にほん]の 漢字[かんじ]". The text in brackets reads the word
+// before it, which starts after a space. Keeps either the words or their readings.
+fn furigana(s: &str, keep_reading: bool) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(open) = rest.find('[') {
+        let Some(close) = rest[open..].find(']').map(|c| open + c) else {
+            break;
+        };
+        let before = &rest[..open];
+        let base = match before.rfind(' ') {
+            Some(space) => {
+                out.push_str(&before[..space]);
+                &before[space + 1..]
+            }
+            None => before,
+        };
+        out.push_str(if keep_reading {
+            &rest[open + 1..close]
+        } else {
+            base
+        });
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// Remove [sound:file.mp3] references to the deck's media.
+fn strip_sounds(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("[sound:") {
+        out.push_str(&rest[..start]);
+        match rest[start..].find(']') {
+            Some(end) => rest = &rest[start + end + 1..],
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    out.push_str(rest);
+    out.trim().to_string()
+}
+
+fn import_anki(args: &AnkiArgs) -> Result<(), String> {
+    let front = FieldRef::parse(&args.front_field)?;
+    let back = FieldRef::parse(&args.back_field)?;
+    let reading = args
+        .reading_field
+        .as_deref()
+        .map(FieldRef::parse)
+        .transpose()?;
+
+    let pack = args.pack.clone().unwrap_or_else(|| {
+        args.file
+            .with_extension("")
+            .file_name()
+            .unwrap_or_default()
+            .into()
+    });
+    let path = user_quotes_dir().join(pack_file_name(&pack));
+    if path.exists() && !args.force {
+        return Err(format!(
+            "{} already exists, use --force to replace it",
+            path.display()
+        ));
+    }
+
+    let extension = args
+        .file
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let notes = match extension.as_str() {
+        "apkg" | "colpkg" => read_package(&args.file)?,
+        "anki2" | "anki21" => read_collection(&args.file)?,
+        _ => read_text(&args.file)?,
+    };
+
+    let clean = |s: &str| {
+        let s = strip_sounds(s);
+        if args.strip_html { strip_html(&s) } else { s }
+    };
+    let no_names = Vec::new();
+    let mut quotes = Vec::new();
+    let mut missing_field = 0;
+    let mut empty = 0;
+    for (note_type, fields) in &notes.notes {
+        let names = notes.field_names.get(note_type).unwrap_or(&no_names);
+        let field = |f: &FieldRef| {
+            f.resolve(names)
+                .and_then(|i| fields.get(i))
+                .map(|s| clean(s))
+        };
+
+        let Some(mut japanese) = field(&front) else {
+            missing_field += 1;
+            continue;
+        };
+        if args.strip_furigana {
+            japanese = furigana(&japanese, false);
+        }
+        if japanese.is_empty() {
+            empty += 1;
+            continue;
+        }
+        // readings in kana or as furigana are turned into romaji
+        let romaji = reading.as_ref().and_then(&field).map(|r| {
+            let kana = if r.contains('[') {
+                furigana(&r, true)
+            } else {
+                r.clone()
+            };
+            kana_to_romaji(&kana).unwrap_or(r)
+        });
+
+        quotes.push(Quote {
+            japanese,
+            translation: field(&back).filter(|t| !t.is_empty()),
+            romaji: romaji.filter(|r| !r.is_empty()),
+            ..Default::default()
+        });
+    }
+
+    if missing_field > 0 {
+        eprintln!("Skipped {missing_field} notes without the requested fields");
+    }
+    if empty > 0 {
+        eprintln!("Skipped {empty} notes with an empty front field");
+    }
+    if quotes.is_empty() {
+        return Err(format!("no notes to import from {}", args.file.display()));
+    }
+
+    let mut tables = ArrayOfTables::new();
+    for (i, quote) in quotes.iter().enumerate() {
+        let mut table = quote_table(quote);
+        if i == 0 {
+            let file = args.file.file_name().unwrap_or_default().to_string_lossy();
+            table
+                .decor_mut()
+                .set_prefix(format!("# Imported from {file}\n\n"));
+        } else {
+            table.decor_mut().set_prefix("\n");
+        }
+        tables.push(table);
+    }
+    let mut doc = DocumentMut::new();
+    doc.insert("quote", Item::ArrayOfTables(tables));
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    }
+    write_document(&path, &doc)?;
+    println!(
+        "Imported {} quotes into {} ({})",
+        quotes.len(),
+        pack_name(&pack),
+        path.display()
+    );
+    Ok(())
+}
+
+pub fn run(from: &ImportFrom) -> Result<(), String> {
+    match from {
+        ImportFrom::Anki(args) => import_anki(args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn furigana_is_removed_or_kept() {
+        let text = "日本[にほん]の 漢字[かんじ]";
+        assert_eq!(furigana(text, false), "日本の漢字");
+        assert_eq!(furigana(text, true), "にほんのかんじ");
+        assert_eq!(furigana("漢字[かんじ]", false), "漢字");
+        assert_eq!(furigana("no brackets", false), "no brackets");
+        // an unclosed bracket is left alone
+        assert_eq!(furigana("漢字[かん", false), "漢字[かん");
+    }
+
+    #[test]
+    fn html_tags_are_removed() {
+        assert_eq!(strip_html("<b>猫</b>に<i>小判</i>"), "猫に小判");
+        assert_eq!(
+            strip_html("古池や<br>蛙飛び込む<BR />水の音"),
+            "古池や\n蛙飛び込む\n水の音"
+        );
+        assert_eq!(strip_html("<div>一</div><div>二</div>"), "一\n二");
+        assert_eq!(strip_html("a < b"), "a < b");
+    }
+
+    #[test]
+    fn html_entities_are_decoded() {
+        assert_eq!(
+            strip_html("&lt;猫&gt; &amp; &quot;犬&quot;&nbsp;&#12354;&#x3044;"),
+            "<猫> & \"犬\" あい"
+        );
+        // unknown entities and lone ampersands stay as they are
+        assert_eq!(strip_html("&bogus; R&D"), "&bogus; R&D");
+    }
+
+    #[test]
+    fn sounds_are_removed() {
+        assert_eq!(strip_sounds("猫[sound:neko.mp3]"), "猫");
+    }
+}
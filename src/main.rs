@@ -1,20 +1,104 @@
+mod cache;
+mod check;
 mod cli;
 mod config;
+mod context;
+mod diagnostic;
 mod display;
+mod export;
+mod formats;
+mod graphics;
+mod import;
+mod manage;
+mod motd;
+mod output;
+mod quiz;
 mod quotes;
+mod raster;
+mod romaji;
+mod state;
 
-use crate::cli::Cli;
+use crate::cli::{Cli, Command, ConfigAction};
 use clap::Parser;
 
 fn main() {
     let cli = Cli::parse();
 
-    // load user config (if exists)
-    let user_cfg = config::load_user_config(cli.config.clone());
+    // load system, user and project configs (if they exist)
+    let layers = match config::load_config_layers(cli.config.clone(), cli.strict) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    // KOTOFETCH_* environment variables
+    let strict = cli.strict || layers.iter().any(|c| c.strict == Some(true));
+    let env_cfg = match config::load_env(strict) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    };
 
     // merge into a runtime Config
-    let runtime = config::make_runtime_config(user_cfg, &cli);
+    let runtime = match config::make_runtime_config(layers, env_cfg, &cli) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    };
+    quotes::allow_command_packs_in(&runtime.command_dirs);
+
+    let result = match &cli.command {
+        Some(Command::Quiz(_)) => {
+            quiz::run(&runtime);
+            Ok(())
+        }
+        Some(Command::Add(args)) => manage::add(&runtime, args),
+        Some(Command::List(args)) => manage::list(&runtime, args),
+        Some(Command::Show { id }) => manage::show(&runtime, id),
+        Some(Command::Remove { id }) => manage::remove(&runtime, id),
+        Some(Command::Edit { id }) => manage::edit(&runtime, id),
+        Some(Command::Fav(args)) => state::fav(&runtime, args),
+        Some(Command::Hide(args)) => state::hide(&runtime, args),
+        Some(Command::Packs) => {
+            manage::packs(&runtime);
+            Ok(())
+        }
+        Some(Command::Export(args)) => export::run(&runtime, args),
+        Some(Command::Motd(args)) => motd::run(&runtime, &cli, args),
+        Some(Command::Render(args)) => raster::run(&runtime, &cli, args),
+        Some(Command::Import { from }) => import::run(from),
+        Some(Command::Config { action }) => {
+            let path = config::config_path(cli.config.clone());
+            let path = || path.clone().ok_or("no config directory found".to_string());
+            match action {
+                ConfigAction::Init { force } => path().and_then(|p| config::init(&p, *force)),
+                ConfigAction::Show => {
+                    config::show(&runtime);
+                    Ok(())
+                }
+                ConfigAction::Path => {
+                    config::print_paths(cli.config.clone());
+                    Ok(())
+                }
+                ConfigAction::Edit => path().and_then(|p| config::edit(&p)),
+            }
+        }
+        Some(Command::Check {
+            files,
+            deny_warnings,
+        }) => check::run(&runtime, files, *deny_warnings),
+        // render output
+        None => display::render(&runtime, &cli),
+    };
 
-    // render output
-    display::render(&runtime, &cli);
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,530 @@
+use crate::cli::{AddArgs, ListArgs};
+use crate::config::{RuntimeConfig, weight_value};
+use crate::display::truncate_to_width;
+use crate::formats::{field_name, format_of_path, parse_pack, toml_format};
+use crate::quotes::{
+    IdMatcher, PackSource, Quote, all_packs, load_source, pack_file_name, pack_id, resolve_pack,
+    short_ids, user_quotes_dir,
+};
+use crate::state::{favorites_file, scratch_file};
+use console::Style;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, value};
+use unicode_width::UnicodeWidthStr;
+
+// Split an ID like "anime:3f9a" into its pack and the ID of the quote in that pack.
+pub fn parse_id(id: &str) -> Result<(PathBuf, String), String> {
+    match id.rsplit_once(':') {
+        Some((pack, key)) if !pack.is_empty() && !key.is_empty() => {
+            Ok((PathBuf::from(pack), key.to_string()))
+        }
+        _ => Err(format!("invalid quote ID '{id}', expected <pack>:<id>")),
+    }
+}
+
+fn read_source(source: &PackSource) -> Result<String, String> {
+    match source {
+        PackSource::File(path) => {
+            fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))
+        }
+        PackSource::Builtin(_, content) => Ok(content.to_string()),
+        PackSource::Stdin => Err("packs read from stdin can't be modified".to_string()),
+    }
+}
+
+// Quotes of a pack in any format. Invalid entries are reported and skipped.
+pub fn read_pack(pack: &Path, search: &[PathBuf]) -> Result<(PackSource, Vec<Quote>), String> {
+    let source = resolve_pack(pack, search).ok_or_else(|| {
+        format!(
+            "pack not found in quotes directories or built-in: {}",
+            pack.display()
+        )
+    })?;
+    let loaded = load_source(&source)?;
+    loaded.errors().for_each(|d| d.print());
+    let quotes = loaded.quotes.into_iter().map(|(q, _)| q).collect();
+    Ok((source, quotes))
+}
+
+// Path of the file for a pack, ready to be modified. Only TOML packs can be modified.
+// Packs found in the user quotes directory or in an extra quotes directory are modified
+// in place. Built-in and system-wide packs are copied to the user quotes directory first
+// so they keep their quotes.
+fn writable_pack(pack: &Path, runtime: &RuntimeConfig) -> Result<PathBuf, String> {
+    let user_dir = user_quotes_dir();
+    let path = user_dir.join(pack_file_name(pack));
+    let source = resolve_pack(pack, &runtime.quote_search_path());
+    if let Some(PackSource::File(found)) = &source
+        && let Some(format) = format_of_path(found).filter(|f| f.name != "TOML")
+    {
+        return Err(format!(
+            "{} is a {} pack, only TOML packs can be modified",
+            found.display(),
+            format.name
+        ));
+    }
+    if let Some(PackSource::File(found)) = &source
+        && (found.starts_with(&user_dir)
+            || *found == favorites_file()
+            || runtime.quotes_dirs.iter().any(|d| found.starts_with(d))
+            || pack.components().count() > 1)
+    {
+        return Ok(found.clone());
+    }
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let content = match &source {
+        Some(source) => read_source(source)?,
+        None => String::new(),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    }
+    fs::write(&path, content).map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+    Ok(path)
+}
+
+pub fn read_document(path: &Path) -> Result<DocumentMut, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    content
+        .parse::<DocumentMut>()
+        .map_err(|e| format!("failed to parse {}: {e}", path.display()))
+}
+
+// Check a TOML pack with the loader itself and count its quotes. Any error fails, as the
+// loader would drop the quote it belongs to; a pack without quotes is fine.
+fn validate_pack(file: &str, content: &str) -> Result<usize, String> {
+    let loaded = parse_pack(toml_format(), file, content);
+    if let Some(error) = loaded.errors().next() {
+        return Err(match error.location {
+            Some((line, col)) => format!("{file}:{line}:{col}: {}", error.message),
+            None => format!("{file}: {}", error.message),
+        });
+    }
+    Ok(loaded.quotes.len())
+}
+
+pub fn write_document(path: &Path, doc: &DocumentMut) -> Result<(), String> {
+    let content = doc.to_string();
+    // never write back something the loader would reject
+    validate_pack(&path.display().to_string(), &content)
+        .map_err(|e| format!("refusing to write invalid pack {e}"))?;
+    fs::write(path, content).map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+// A [[quote]] table with the fields that are set.
+pub fn quote_table(quote: &Quote) -> Table {
+    let mut table = Table::new();
+    table["japanese"] = value(&quote.japanese);
+    if let Some(t) = &quote.translation {
+        table["translation"] = value(t);
+    }
+    if let Some(r) = &quote.romaji {
+        table["romaji"] = value(r);
+    }
+    if let Some(s) = &quote.source {
+        table["source"] = value(s);
+    }
+    if let Some(w) = quote.weight {
+        table["weight"] = Item::Value(weight_value(w));
+    }
+    if let Some(id) = &quote.id {
+        table["id"] = value(id);
+    }
+    if let Some(s) = &quote.season {
+        table["season"] = value(s);
+    }
+    if let Some(t) = &quote.time {
+        table["time"] = value(t);
+    }
+    if let Some(months) = &quote.months {
+        table["months"] = value(
+            months
+                .iter()
+                .map(|&m| i64::from(m))
+                .collect::<toml_edit::Array>(),
+        );
+    }
+    table
+}
+
+// Position of the [[quote]] table `key` names, see IdMatcher.
+pub fn find_table(tables: &ArrayOfTables, pack: &Path, key: &str) -> Result<usize, String> {
+    // the keys may be aliases, like `jp` for `japanese`
+    let text = |table: &Table, name: &str| {
+        table
+            .iter()
+            .find(|(key, _)| field_name(key) == Some(name))
+            .and_then(|(_, item)| item.as_str())
+            .map(str::to_string)
+    };
+    let mut matcher = IdMatcher::new(key);
+    for (i, table) in tables.iter().enumerate() {
+        let quote = Quote {
+            japanese: text(table, "japanese").unwrap_or_default(),
+            id: text(table, "id").filter(|id| !id.is_empty()),
+            ..Default::default()
+        };
+        matcher.check(i, &quote);
+    }
+    matcher.finish(&pack_id(pack))
+}
+
+fn quote_tables<'a>(doc: &'a mut DocumentMut, id: &str) -> Result<&'a mut ArrayOfTables, String> {
+    doc.get_mut("quote")
+        .and_then(Item::as_array_of_tables_mut)
+        .ok_or_else(|| format!("no quote found for '{id}'"))
+}
+
+// Open a file in $VISUAL, $EDITOR or vi and wait for it to exit.
+pub fn open_in_editor(path: &Path) -> Result<(), String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut parts = editor.split_whitespace();
+    let status = process::Command::new(parts.next().unwrap_or("vi"))
+        .args(parts)
+        .arg(path)
+        .status()
+        .map_err(|e| format!("failed to run {editor}: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{editor} exited with an error"))
+    }
+}
+
+// Ask for a field on stdin, empty answers are None.
+fn read_field(name: &str) -> Option<String> {
+    print!("{name}: ");
+    io::stdout().flush().ok();
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).ok()?;
+    let line = line.trim();
+    if line.is_empty() {
+        None
+    } else {
+        Some(line.to_string())
+    }
+}
+
+pub fn add(runtime: &RuntimeConfig, args: &AddArgs) -> Result<(), String> {
+    let quote = match &args.japanese {
+        Some(japanese) => Quote {
+            japanese: japanese.clone(),
+            translation: args.translation.clone(),
+            romaji: args.romaji.clone(),
+            source: args.source.clone(),
+            weight: args.weight,
+            ..Default::default()
+        },
+        None => Quote {
+            japanese: read_field("japanese").ok_or("japanese text is required")?,
+            translation: args
+                .translation
+                .clone()
+                .or_else(|| read_field("translation")),
+            romaji: args.romaji.clone().or_else(|| read_field("romaji")),
+            source: args.source.clone().or_else(|| read_field("source")),
+            weight: args.weight,
+            ..Default::default()
+        },
+    };
+
+    let path = writable_pack(&args.pack, runtime)?;
+    let mut doc = read_document(&path)?;
+
+    let mut table = quote_table(&quote);
+    if !doc.is_empty() {
+        table.decor_mut().set_prefix("\n");
+    }
+
+    let tables = doc
+        .entry("quote")
+        .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+        .ok_or_else(|| format!("'quote' is not an array of tables in {}", path.display()))?;
+    tables.push(table);
+
+    write_document(&path, &doc)?;
+    // the new quote is the last one of the file
+    let (_, quotes) = read_pack(&path, &[])?;
+    let id = short_ids(&quotes)
+        .pop()
+        .unwrap_or_else(|| quote.short_id(0));
+    println!("Added {}:{id}", pack_id(&args.pack));
+    Ok(())
+}
+
+pub fn list(runtime: &RuntimeConfig, args: &ListArgs) -> Result<(), String> {
+    let packs = args.pack.as_ref().unwrap_or(&runtime.modes);
+    let search = runtime.quote_search_path();
+
+    let mut rows = Vec::new();
+    for pack in packs {
+        let (_, quotes) = read_pack(pack, &search)?;
+        let ids = short_ids(&quotes);
+        for (q, id) in quotes.into_iter().zip(ids) {
+            let japanese = q.japanese.lines().next().unwrap_or_default().to_string();
+            rows.push((
+                format!("{}:{}", pack_id(pack), id),
+                truncate_to_width(&japanese, 40),
+                q.translation.unwrap_or_default(),
+            ));
+        }
+    }
+
+    let id_width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0);
+    let jap_width = rows
+        .iter()
+        .map(|r| UnicodeWidthStr::width(r.1.as_str()))
+        .max()
+        .unwrap_or(0);
+    for (id, japanese, translation) in rows {
+        let pad = jap_width - UnicodeWidthStr::width(japanese.as_str());
+        let line = format!(
+            "{:<id_width$}  {}{}  {}",
+            id,
+            japanese,
+            " ".repeat(pad),
+            translation
+        );
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+pub fn show(runtime: &RuntimeConfig, id: &str) -> Result<(), String> {
+    let (pack, key) = parse_id(id)?;
+    let (source, quotes) = read_pack(&pack, &runtime.quote_search_path())?;
+    let mut matcher = IdMatcher::new(&key);
+    quotes
+        .iter()
+        .enumerate()
+        .for_each(|(i, q)| matcher.check(i, q));
+    let index = matcher.finish(&pack_id(&pack))?;
+    let quote = &quotes[index];
+
+    println!(
+        "{}:{} ({source})",
+        pack_id(&pack),
+        short_ids(&quotes)[index]
+    );
+    println!("japanese:    {}", quote.japanese);
+    if let Some(t) = &quote.translation {
+        println!("translation: {t}");
+    }
+    if let Some(r) = &quote.romaji {
+        println!("romaji:      {r}");
+    }
+    if let Some(s) = &quote.source {
+        println!("source:      {s}");
+    }
+    Ok(())
+}
+
+pub fn remove(runtime: &RuntimeConfig, id: &str) -> Result<(), String> {
+    let (pack, key) = parse_id(id)?;
+    let path = writable_pack(&pack, runtime)?;
+    let mut doc = read_document(&path)?;
+
+    let tables = quote_tables(&mut doc, id)?;
+    let index = find_table(tables, &pack, &key)?;
+    tables.remove(index);
+
+    write_document(&path, &doc)?;
+    println!("Removed {id}");
+    Ok(())
+}
+
+pub fn edit(runtime: &RuntimeConfig, id: &str) -> Result<(), String> {
+    let (pack, key) = parse_id(id)?;
+    let path = writable_pack(&pack, runtime)?;
+    let mut doc = read_document(&path)?;
+
+    let tables = quote_tables(&mut doc, id)?;
+    let index = find_table(tables, &pack, &key)?;
+    let original = tables
+        .get(index)
+        .cloned()
+        .ok_or_else(|| format!("no quote found for '{id}'"))?;
+
+    // write the entry alone to a temporary file
+    let mut entry = original.clone();
+    entry.decor_mut().set_prefix("");
+    let mut single = ArrayOfTables::new();
+    single.push(entry);
+    let mut scratch = DocumentMut::new();
+    scratch.insert("quote", Item::ArrayOfTables(single));
+
+    let (tmp, mut file) = scratch_file("edit", "toml")?;
+    if let Err(e) = file.write_all(scratch.to_string().as_bytes()) {
+        fs::remove_file(&tmp).ok();
+        return Err(format!("failed to write {}: {e}", tmp.display()));
+    }
+    drop(file);
+
+    let status = open_in_editor(&tmp);
+    let edited = fs::read_to_string(&tmp);
+    fs::remove_file(&tmp).ok();
+    status.map_err(|e| format!("{e}, quote left unchanged"))?;
+    let edited = edited.map_err(|e| format!("failed to read {}: {e}", tmp.display()))?;
+
+    let mut table = edited_table(&edited, id)?;
+
+    // keep the comments above the entry and its place in the file
+    *table.decor_mut() = original.decor().clone();
+    if let Some(position) = original.position() {
+        table.set_position(position);
+    }
+    if let Some(slot) = quote_tables(&mut doc, id)?.get_mut(index) {
+        *slot = table;
+    }
+
+    write_document(&path, &doc)?;
+    println!("Updated {id}");
+    Ok(())
+}
+
+// The [[quote]] table of an edited file, if the loader accepts it.
+fn edited_table(edited: &str, id: &str) -> Result<Table, String> {
+    let count =
+        validate_pack("edited quote", edited).map_err(|e| format!("{e}, quote left unchanged"))?;
+    if count != 1 {
+        return Err("edited file must contain exactly one [[quote]]".to_string());
+    }
+    let mut doc = edited
+        .parse::<DocumentMut>()
+        .map_err(|e| format!("edited quote is invalid, left unchanged: {e}"))?;
+    quote_tables(&mut doc, id)?
+        .get(0)
+        .cloned()
+        .ok_or_else(|| "edited file must contain exactly one [[quote]]".to_string())
+}
+
+// List every pack name with the file it resolves to and the ones it shadows.
+pub fn packs(runtime: &RuntimeConfig) {
+    let search = runtime.quote_search_path();
+    let dim = Style::new().dim();
+
+    println!("Search path, most important first:");
+    for dir in &search {
+        let missing = if dir.is_dir() { "" } else { " (not found)" };
+        println!("  {}{}", dir.display(), dim.apply_to(missing));
+    }
+    println!("  built-in");
+    println!();
+
+    let packs = all_packs(&search);
+    let width = packs.keys().map(|n| n.len()).max().unwrap_or(0);
+    for (name, sources) in packs {
+        let mut sources = sources.into_iter();
+        if let Some(winner) = sources.next() {
+            println!("{name:<width$}  {winner}");
+        }
+        for shadowed in sources {
+            let line = format!("{:<width$}    shadows {shadowed}", "");
+            println!("{}", dim.apply_to(line));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory for a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("kotofetch-{name}-{}", process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn quotes_of(path: &Path) -> Vec<Quote> {
+        let (_, quotes) = read_pack(path, &[]).unwrap();
+        quotes
+    }
+
+    #[test]
+    fn removing_the_last_quote_leaves_an_empty_pack() {
+        let path = test_dir("remove-last").join("pack.toml");
+        fs::write(&path, "[[quote]]\njapanese = \"猫\"\n").unwrap();
+        let cat = Quote {
+            japanese: "猫".to_string(),
+            ..Default::default()
+        };
+        let id = format!("{}:{}", path.display(), cat.short_id(4));
+
+        remove(&RuntimeConfig::default(), &id).unwrap();
+        assert!(quotes_of(&path).is_empty());
+
+        let args = AddArgs {
+            pack: path.clone(),
+            japanese: Some("犬".to_string()),
+            translation: None,
+            romaji: None,
+            source: None,
+            weight: None,
+        };
+        add(&RuntimeConfig::default(), &args).unwrap();
+        assert_eq!(quotes_of(&path)[0].japanese, "犬");
+    }
+
+    #[test]
+    fn aliased_packs_can_be_modified() {
+        let path = test_dir("aliases").join("pack.toml");
+        fs::write(
+            &path,
+            "[[quote]]\njp = \"猫\"\nenglish = \"cat\"\n\n[[quote]]\njp = \"鳥\"\n",
+        )
+        .unwrap();
+        let args = AddArgs {
+            pack: path.clone(),
+            japanese: Some("犬".to_string()),
+            translation: Some("dog".to_string()),
+            romaji: None,
+            source: None,
+            weight: None,
+        };
+        add(&RuntimeConfig::default(), &args).unwrap();
+        assert_eq!(quotes_of(&path).len(), 3);
+
+        // the hash of an aliased quote names it too
+        let cat = Quote {
+            japanese: "猫".to_string(),
+            ..Default::default()
+        };
+        let id = format!("{}:{}", path.display(), cat.short_id(8));
+        remove(&RuntimeConfig::default(), &id).unwrap();
+        let left: Vec<String> = quotes_of(&path).into_iter().map(|q| q.japanese).collect();
+        assert_eq!(left, ["鳥", "犬"]);
+    }
+
+    #[test]
+    fn quotes_the_loader_drops_are_not_written() {
+        let path = test_dir("bad-season").join("pack.toml");
+        let original = "[[quote]]\njapanese = \"猫\"\n";
+        fs::write(&path, original).unwrap();
+
+        let mut doc = read_document(&path).unwrap();
+        doc["quote"][0]["season"] = value("foo");
+        let error = write_document(&path, &doc).unwrap_err();
+        assert!(error.contains("pack.toml:3:"), "{error}");
+        assert!(error.contains("season"), "{error}");
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+
+        let error =
+            edited_table("[[quote]]\njapanese = \"猫\"\nseason = \"foo\"\n", "p:0").unwrap_err();
+        assert!(error.contains("edited quote:3:"), "{error}");
+        assert!(edited_table("[[quote]]\njp = \"猫\"\nseason = \"summer\"\n", "p:0").is_ok());
+        assert!(edited_table("", "p:0").is_err());
+    }
+}
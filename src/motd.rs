@@ -0,0 +1,218 @@
+// `kotofetch motd`: a quote as a login banner. Nothing depends on the terminal, which
+// isn't there under cron or PAM: the width is given and the output is plain text.
+use crate::cli::{Cli, MotdArgs};
+use crate::config::{RuntimeConfig, TranslationMode};
+use crate::display::{Layout, choose_quote};
+use crate::quotes::Quote;
+use crate::romaji::kana_to_romaji;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use unicode_width::UnicodeWidthChar;
+
+pub fn run(runtime: &RuntimeConfig, cli: &Cli, args: &MotdArgs) -> Result<(), String> {
+    console::set_colors_enabled(false);
+    let quote = choose_quote(runtime, cli)?;
+    let (runtime, quote) = if args.ascii {
+        ascii_quote(runtime, quote)
+    } else {
+        (runtime.clone(), quote)
+    };
+    let max_width = cli.max_width.unwrap_or(args.columns);
+    let lines =
+        Layout::new(&runtime, &quote).boxed(Some(max_width), Some(args.columns), cli.max_lines);
+    let mut content = String::new();
+    for line in lines {
+        let line = if args.ascii { to_ascii(&line) } else { line };
+        content.push_str(line.trim_end());
+        content.push('\n');
+    }
+
+    match &args.output {
+        Some(path) => write_atomic(path, content.as_bytes()),
+        None => io::stdout()
+            .write_all(content.as_bytes())
+            .map_err(|e| format!("failed to write: {e}")),
+    }
+}
+
+// The quote in romaji with its English translation, or the translation alone when it
+// can't be read, and the settings to show it with.
+fn ascii_quote(runtime: &RuntimeConfig, quote: Quote) -> (RuntimeConfig, Quote) {
+    let mut runtime = runtime.clone();
+    runtime.font_size = "small".to_string();
+    let show_translation = !matches!(runtime.show_translation, TranslationMode::None);
+    if show_translation {
+        runtime.show_translation = TranslationMode::English;
+    }
+    let romaji = quote
+        .romaji
+        .clone()
+        .or_else(|| kana_to_romaji(&quote.japanese));
+    let quote = match (romaji, quote.translation.clone()) {
+        (Some(romaji), translation) => Quote {
+            japanese: romaji,
+            translation: translation.filter(|_| show_translation),
+            ..quote
+        },
+        (None, Some(translation)) => Quote {
+            japanese: translation,
+            translation: None,
+            ..quote
+        },
+        (None, None) => quote,
+    };
+    (runtime, quote)
+}
+
+// The line with every other character in ASCII, as wide as it was so the box holds.
+fn to_ascii(line: &str) -> String {
+    let mut out = String::new();
+    for c in line.chars() {
+        let ascii = match c {
+            c if c.is_ascii() => c,
+            '╭' | '╮' | '╰' | '╯' | '┌' | '┐' | '└' | '┘' => '+',
+            '─' | '—' | '–' => '-',
+            '│' => '|',
+            '’' | '‘' => '\'',
+            '“' | '”' => '"',
+            'ā' | 'â' => 'a',
+            'ī' | 'î' => 'i',
+            'ū' | 'û' => 'u',
+            'ē' | 'ê' => 'e',
+            'ō' | 'ô' => 'o',
+            'Ā' => 'A',
+            'Ī' => 'I',
+            'Ū' => 'U',
+            'Ē' => 'E',
+            'Ō' => 'O',
+            '…' => '.',
+            c => {
+                let width = UnicodeWidthChar::width(c).unwrap_or(0);
+                out.push_str(&"?".repeat(width));
+                continue;
+            }
+        };
+        out.push(ascii);
+    }
+    out
+}
+
+// Write next to the file and rename it, so a login never shows half a banner. The file
+// keeps its permissions. The name next to it is random and made fresh, so nothing placed
+// there beforehand is written through.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), String> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let failed = |e: io::Error| format!("failed to write {}: {e}", path.display());
+    for _ in 0..100 {
+        let tmp = path.with_file_name(format!(".{name}.{:016x}.tmp", rand::random::<u64>()));
+        let mut file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(failed(e)),
+        };
+        let result = (|| {
+            file.write_all(content)?;
+            if let Ok(metadata) = fs::metadata(path) {
+                file.set_permissions(metadata.permissions())?;
+            }
+            file.sync_all()?;
+            fs::rename(&tmp, path)
+        })();
+        return result.map_err(|e| {
+            fs::remove_file(&tmp).ok();
+            failed(e)
+        });
+    }
+    Err(format!(
+        "failed to write {}: no free name for the temporary file",
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("kotofetch-{name}-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file_and_leaves_nothing_behind() {
+        let dir = test_dir("motd-write");
+        let path = dir.join("motd");
+        write_atomic(&path, b"first\n").unwrap();
+        write_atomic(&path, b"second\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["motd"]);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = test_dir("motd-mode");
+        let path = dir.join("motd");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        write_atomic(&path, b"new").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn write_atomic_reports_a_missing_folder() {
+        let dir = test_dir("motd-missing");
+        let path = dir.join("nowhere").join("motd");
+        let err = write_atomic(&path, b"x").unwrap_err();
+        assert!(err.starts_with("failed to write"), "{err}");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn to_ascii_keeps_the_width() {
+        let line = "│ 猫 “café” — ō │";
+        let ascii = to_ascii(line);
+        assert_eq!(ascii, "| ?? \"caf?\" - o |");
+        assert_eq!(
+            unicode_width::UnicodeWidthStr::width(ascii.as_str()),
+            unicode_width::UnicodeWidthStr::width(line)
+        );
+    }
+
+    #[test]
+    fn ascii_quote_uses_romaji_or_the_translation() {
+        let runtime = RuntimeConfig::default();
+        let kana = Quote {
+            japanese: "ねこ".to_string(),
+            translation: Some("cat".to_string()),
+            ..Quote::default()
+        };
+        let (runtime_used, quote) = ascii_quote(&runtime, kana);
+        assert_eq!(quote.japanese, "neko");
+        assert_eq!(runtime_used.font_size, "small");
+
+        let kanji = Quote {
+            japanese: "猫".to_string(),
+            translation: Some("cat".to_string()),
+            ..Quote::default()
+        };
+        let (_, quote) = ascii_quote(&runtime, kanji);
+        assert_eq!(quote.japanese, "cat");
+        assert_eq!(quote.translation, None);
+    }
+}
@@ -0,0 +1,224 @@
+// Outputs other than the terminal: status bars and shell prompts. They show the quote
+// on one line, styled with markup of their own rather than raw ANSI escapes.
+use crate::cli::OutputFormat;
+use crate::config::RuntimeConfig;
+use crate::display::{Layout, Part, color_to_hex};
+use crate::quotes::{Quote, pack_id};
+use serde_json::json;
+use std::path::Path;
+
+// The quote for `format`, from `pack` when known, cut to `max_width` columns when set.
+pub fn format(
+    runtime: &RuntimeConfig,
+    format: OutputFormat,
+    quote: &Quote,
+    pack: Option<&Path>,
+    max_width: Option<usize>,
+) -> String {
+    let layout = Layout::new(runtime, quote);
+    let parts = layout.oneline_parts(max_width);
+    let text: String = parts.iter().map(|(_, text)| text.as_str()).collect();
+    match format {
+        OutputFormat::Terminal | OutputFormat::Svg | OutputFormat::Html => {
+            layout.oneline(max_width)
+        }
+        OutputFormat::Tmux => markup(runtime, &parts, "#", |part, color, text| {
+            let bold = if part == Part::Japanese && runtime.bold {
+                ",bold"
+            } else {
+                ""
+            };
+            format!("#[fg={color}{bold}]{text}#[default]")
+        }),
+        OutputFormat::Polybar => markup(runtime, &parts, "%", |_, color, text| {
+            format!("%{{F{color}}}{text}%{{F-}}")
+        }),
+        OutputFormat::Waybar => json!({
+            "text": escape_pango(&text),
+            "tooltip": escape_pango(&tooltip(quote)),
+            "class": pack.map_or("kotofetch".to_string(), pack_id),
+        })
+        .to_string(),
+        OutputFormat::I3blocks => {
+            let japanese = parts.iter().find(|(part, _)| *part == Part::Japanese);
+            let mut block = json!({
+                "full_text": text,
+                "short_text": japanese.map_or("", |(_, text)| text.as_str()),
+            });
+            if let Some(color) = color_to_hex(&runtime.quote_color) {
+                block["color"] = json!(color);
+            }
+            block.to_string()
+        }
+        OutputFormat::ZshPrompt => prompt(&layout, max_width, zsh_escape, "%{", "%}"),
+        OutputFormat::BashPrompt => prompt(&layout, max_width, bash_escape, "\\[", "\\]"),
+    }
+}
+
+// The color a part is shown in, None for plain text.
+pub fn part_color(runtime: &RuntimeConfig, part: Part) -> Option<String> {
+    match part {
+        Part::Japanese => color_to_hex(&runtime.quote_color),
+        Part::Separator => None,
+        Part::Translation => color_to_hex(&runtime.translation_color),
+        Part::Source => color_to_hex("dim"),
+    }
+}
+
+// The parts wrapped in `tag`, with `special`, which starts the bar's own markup, doubled
+// in the text so it shows as is.
+fn markup(
+    runtime: &RuntimeConfig,
+    parts: &[(Part, String)],
+    special: &str,
+    tag: impl Fn(Part, &str, &str) -> String,
+) -> String {
+    parts
+        .iter()
+        .map(|(part, text)| {
+            let text = text.replace(special, &special.repeat(2));
+            match part_color(runtime, *part) {
+                Some(color) => tag(*part, &color, &text),
+                None => text,
+            }
+        })
+        .collect()
+}
+
+// The whole quote, a line per field.
+fn tooltip(quote: &Quote) -> String {
+    let mut lines = vec![quote.japanese.trim().to_string()];
+    lines.extend(quote.romaji.iter().map(|r| r.trim().to_string()));
+    lines.extend(quote.translation.iter().map(|t| t.trim().to_string()));
+    lines.extend(quote.source.iter().map(|s| format!("— {}", s.trim())));
+    lines.join("\n")
+}
+
+// Waybar reads text and tooltips as Pango markup.
+fn escape_pango(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// zsh reads % sequences in the prompt. `$` and backticks are left alone: with
+// prompt_subst the output comes through `$(...)` or `${var}`, which aren't expanded again.
+fn zsh_escape(c: char, out: &mut String) {
+    if c == '%' {
+        out.push('%');
+    }
+    out.push(c);
+}
+
+// bash decodes the backslashes of PS1, then expands it like a double quoted string, so
+// the characters special to either are escaped twice: `\\$` turns into `\$`, then `$`.
+fn bash_escape(c: char, out: &mut String) {
+    match c {
+        '\\' => out.push_str("\\\\\\"),
+        '$' | '`' => out.push_str("\\\\"),
+        _ => {}
+    }
+    out.push(c);
+}
+
+// The ANSI line for a prompt: the text escaped for the shell, and every escape sequence
+// between `open` and `close` so the shell doesn't count it in the prompt width.
+fn prompt(
+    layout: &Layout,
+    max_width: Option<usize>,
+    escape: fn(char, &mut String),
+    open: &str,
+    close: &str,
+) -> String {
+    // the prompt is read from a pipe, colors are wanted anyway
+    console::set_colors_enabled(true);
+    let line = layout.oneline(max_width);
+    let mut out = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            out.push_str(open);
+            out.push(c);
+            for c in chars.by_ref() {
+                out.push(c);
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            out.push_str(close);
+        } else {
+            escape(c, &mut out);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    const HOSTILE: &str = "$(echo pwned) `id` ${HOME} \\$PATH \\ 100%";
+
+    fn hostile_prompt(format: OutputFormat) -> String {
+        let runtime = RuntimeConfig {
+            font_size: "small".to_string(),
+            ..RuntimeConfig::default()
+        };
+        let quote = Quote {
+            japanese: HOSTILE.to_string(),
+            ..Quote::default()
+        };
+        super::format(&runtime, format, &quote, None, None)
+    }
+
+    #[test]
+    fn bash_prompt_shows_hostile_quote_as_is() {
+        let ps1 = hostile_prompt(OutputFormat::BashPrompt);
+        // bash expands PS1 the way it does a prompt with ${var@P}
+        let Ok(output) = Command::new("bash")
+            .args(["-c", r#"printf %s "${PS@P}""#])
+            .env("PS", &ps1)
+            .output()
+        else {
+            return;
+        };
+        let shown = String::from_utf8_lossy(&output.stdout);
+        let shown = console::strip_ansi_codes(&shown).replace(['\x01', '\x02'], "");
+        assert_eq!(shown, HOSTILE);
+    }
+
+    #[test]
+    fn zsh_prompt_doubles_percent_only() {
+        let prompt = hostile_prompt(OutputFormat::ZshPrompt);
+        let text = console::strip_ansi_codes(&prompt)
+            .replace("%{", "")
+            .replace("%}", "");
+        assert_eq!(text, HOSTILE.replace('%', "%%"));
+    }
+
+    fn bar(format: OutputFormat) -> String {
+        let quote = Quote {
+            japanese: "#1 で 100% #[bold] %{F-}".to_string(),
+            translation: Some("No. #1".to_string()),
+            ..Quote::default()
+        };
+        super::format(&RuntimeConfig::default(), format, &quote, None, None)
+    }
+
+    #[test]
+    fn tmux_doubles_hashes_in_the_quote() {
+        let line = bar(OutputFormat::Tmux);
+        assert!(line.contains("##1 で 100% ##[bold] %{F-}"), "{line}");
+        assert!(line.contains("No. ##1"), "{line}");
+        assert!(line.ends_with("#[default]"), "{line}");
+    }
+
+    #[test]
+    fn polybar_doubles_percents_in_the_quote() {
+        let line = bar(OutputFormat::Polybar);
+        assert!(line.contains("#1 で 100%% #[bold] %%{F-}"), "{line}");
+        assert!(line.contains("No. #1"), "{line}");
+        assert!(line.ends_with("%{F-}"), "{line}");
+    }
+}
@@ -0,0 +1,201 @@
+use crate::config::{QuizKind, RuntimeConfig};
+use crate::quotes::{Quote, load_pool};
+use crate::romaji::normalize_romaji;
+use console::Style;
+use rand::prelude::*;
+use std::io::{self, BufRead, Write};
+
+// Read one trimmed line from stdin, None on EOF.
+fn prompt(input: &mut impl BufRead, text: &str) -> Option<String> {
+    print!("{text}");
+    io::stdout().flush().ok();
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim().to_string()),
+    }
+}
+
+// Ask for the English meaning as a multiple choice question.
+// Distractors are taken from the translations of other quotes in the pool.
+fn ask_meaning(
+    quote: &Quote,
+    pool: &[Quote],
+    choices: usize,
+    rng: &mut StdRng,
+    input: &mut impl BufRead,
+) -> Option<(bool, String)> {
+    let answer = quote.translation.as_deref()?.trim().to_string();
+
+    let mut distractors: Vec<&str> = pool
+        .iter()
+        .filter_map(|q| q.translation.as_deref().map(str::trim))
+        .filter(|t| t.trim() != answer)
+        .collect();
+    distractors.sort();
+    distractors.dedup();
+    distractors.shuffle(rng);
+
+    let mut options: Vec<String> = distractors
+        .into_iter()
+        .take(choices.saturating_sub(1))
+        .map(str::to_string)
+        .collect();
+    options.push(answer.clone());
+    options.shuffle(rng);
+
+    for (i, option) in options.iter().enumerate() {
+        println!("  {}) {}", i + 1, option);
+    }
+
+    let reply = prompt(input, &format!("Answer [1-{}]: ", options.len()))?;
+    let correct = reply
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| options.get(n.wrapping_sub(1)))
+        .is_some_and(|chosen| *chosen == answer);
+    Some((correct, answer))
+}
+
+// Ask for the reading, typed as romaji.
+fn ask_reading(quote: &Quote, input: &mut impl BufRead) -> Option<(bool, String)> {
+    let answer = quote.romaji.as_deref()?.trim().to_string();
+    let reply = prompt(input, "Reading (romaji): ")?;
+    let correct = !reply.is_empty() && normalize_romaji(&reply) == normalize_romaji(&answer);
+    Some((correct, answer))
+}
+
+pub fn run(runtime: &RuntimeConfig) {
+    let seed = if runtime.seed == 0 {
+        rand::random::<u64>()
+    } else {
+        runtime.seed
+    };
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let packs = runtime.quiz_packs.as_ref().unwrap_or(&runtime.modes);
+    let pool = load_pool(packs, &runtime.quote_search_path());
+
+    // only keep quotes that can be asked with the selected kind
+    let mut candidates: Vec<&Quote> = pool
+        .iter()
+        .filter(|q| match runtime.quiz_kind {
+            QuizKind::Meaning => q.translation.is_some(),
+            QuizKind::Reading => q.romaji.is_some(),
+            QuizKind::Mixed => q.translation.is_some() || q.romaji.is_some(),
+        })
+        .collect();
+
+    if candidates.is_empty() {
+        eprintln!("No quotes available for this quiz");
+        return;
+    }
+
+    candidates.shuffle(&mut rng);
+    candidates.truncate(runtime.quiz_questions);
+
+    let jap_style = Style::new().bold();
+    let good = Style::new().green();
+    let bad = Style::new().red();
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+
+    let total = candidates.len();
+    let mut asked = 0;
+    let mut score = 0;
+    for (i, quote) in candidates.into_iter().enumerate() {
+        let kind = match runtime.quiz_kind {
+            QuizKind::Mixed if quote.translation.is_none() => QuizKind::Reading,
+            QuizKind::Mixed if quote.romaji.is_none() => QuizKind::Meaning,
+            QuizKind::Mixed if rng.random_bool(0.5) => QuizKind::Reading,
+            QuizKind::Mixed => QuizKind::Meaning,
+            ref k => k.clone(),
+        };
+
+        println!();
+        println!(
+            "[{}/{}] {}",
+            i + 1,
+            total,
+            jap_style.apply_to(&quote.japanese)
+        );
+
+        let result = match kind {
+            QuizKind::Reading => ask_reading(quote, &mut input),
+            _ => ask_meaning(quote, &pool, runtime.quiz_choices, &mut rng, &mut input),
+        };
+
+        // stop on EOF
+        let Some((correct, answer)) = result else {
+            println!();
+            break;
+        };
+
+        asked += 1;
+        if correct {
+            score += 1;
+            println!("{}", good.apply_to("✓ Correct"));
+        } else {
+            println!("{} {}", bad.apply_to("✗ Wrong, answer:"), answer);
+        }
+    }
+
+    println!();
+    if asked > 0 {
+        println!("Score: {}/{} ({}%)", score, asked, score * 100 / asked);
+    } else {
+        println!("Score: 0/0");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn quote(translation: &str, romaji: &str) -> Quote {
+        Quote {
+            japanese: "東京".to_string(),
+            translation: Some(translation.to_string()),
+            romaji: Some(romaji.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reading_ignores_macrons_case_and_spaces() {
+        let quote = quote("Tokyo", "Tōkyō");
+        for reply in ["tokyo\n", "Toukyou\n", "TO KYO\n"] {
+            let (correct, answer) = ask_reading(&quote, &mut Cursor::new(reply)).unwrap();
+            assert!(correct, "{reply:?}");
+            assert_eq!(answer, "Tōkyō");
+        }
+        let (correct, _) = ask_reading(&quote, &mut Cursor::new("kyoto\n")).unwrap();
+        assert!(!correct);
+        let (correct, _) = ask_reading(&quote, &mut Cursor::new("\n")).unwrap();
+        assert!(!correct);
+        assert!(ask_reading(&quote, &mut Cursor::new("")).is_none());
+    }
+
+    #[test]
+    fn meaning_is_chosen_by_number() {
+        let quote = quote("Tokyo", "tokyo");
+        let pool = vec![quote.clone(), self::quote("Kyoto", "kyoto")];
+        // the same seed gives the same order of the options
+        let order = |reply: &str| {
+            let mut rng = StdRng::seed_from_u64(1);
+            ask_meaning(
+                &quote,
+                &pool,
+                4,
+                &mut rng,
+                &mut Cursor::new(reply.to_string()),
+            )
+            .unwrap()
+            .0
+        };
+        assert_ne!(order("1\n"), order("2\n"));
+        assert!(!order("3\n"));
+        assert!(!order("Tokyo\n"));
+    }
+}
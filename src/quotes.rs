@@ -1,21 +1,951 @@
-use serde::Deserialize;
+use crate::cache::{self, FNV_OFFSET, PackIndex, fnv1a};
+use crate::diagnostic::{Diagnostic, Level};
+use crate::formats::{
+    FORMATS, Loaded, Location, detect_format, format_by_name, format_of_path, parse_pack,
+    sniff_format, stream_pack, toml_format,
+};
+use crate::state::{FAVORITES_MODE, favorites_file};
+use rand::Rng;
+use rand::seq::IteratorRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::OnceLock;
 
 pub static BUILTIN_QUOTES: &[(&str, &str)] = &[
     ("anime.toml", include_str!("../quotes/anime.toml")),
     ("proverb.toml", include_str!("../quotes/proverb.toml")),
     ("haiku.toml", include_str!("../quotes/haiku.toml")),
+    // special days, see context::SPECIAL_DAYS
+    ("shogatsu.toml", include_str!("../quotes/shogatsu.toml")),
+    ("tanabata.toml", include_str!("../quotes/tanabata.toml")),
+    ("obon.toml", include_str!("../quotes/obon.toml")),
 ];
 
-#[derive(Deserialize, Debug, Clone)]
+// Keys accepted in a [[quote]] table.
+pub const QUOTE_KEYS: &[&str] = &[
+    "japanese",
+    "translation",
+    "romaji",
+    "source",
+    "weight",
+    "id",
+    "season",
+    "time",
+    "months",
+];
+
+// Hash IDs are shown with at least this many characters.
+const SHORT_ID_LEN: usize = 4;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
 pub struct Quote {
     pub japanese: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub translation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub romaji: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<String>,
+    // how likely the quote is to be picked, relative to the others of its pack (1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+    // stable ID within its pack, instead of the content hash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    // when the quote fits, for contextual selection: one of SEASONS, one of TIMES and
+    // months from 1 to 12
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub season: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub months: Option<Vec<u32>>,
+}
+
+impl Quote {
+    // Hash of the japanese text, so the ID survives edits of the other fields and moves
+    // within the pack.
+    pub fn hash_id(&self) -> String {
+        let hash = fnv1a(FNV_OFFSET, self.japanese.trim().as_bytes());
+        format!("{hash:016x}")
+    }
+
+    // The explicit ID, or the content hash cut to `len` characters.
+    pub fn short_id(&self, len: usize) -> String {
+        match &self.id {
+            Some(id) => id.clone(),
+            None => self.hash_id()[..len.clamp(SHORT_ID_LEN, 16)].to_string(),
+        }
+    }
+}
+
+// The IDs to show for the quotes of a pack: explicit IDs, and hash IDs as short as
+// they can be while staying unique in the pack, like git does with commits.
+pub fn short_ids(quotes: &[Quote]) -> Vec<String> {
+    let hashes: Vec<Option<String>> = quotes
+        .iter()
+        .map(|q| q.id.is_none().then(|| q.hash_id()))
+        .collect();
+    let mut sorted: Vec<&str> = hashes.iter().flatten().map(String::as_str).collect();
+    sorted.sort_unstable();
+    // a hash needs one character more than it shares with its closest neighbours
+    let common = |a: &str, b: &str| a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count();
+    let needed = |hash: &str| {
+        let first = sorted.partition_point(|h| *h < hash);
+        let last = sorted.partition_point(|h| *h <= hash);
+        let before = first.checked_sub(1).map_or(0, |i| common(sorted[i], hash));
+        let after = sorted.get(last).map_or(0, |h| common(h, hash));
+        before.max(after) + 1
+    };
+    quotes
+        .iter()
+        .zip(&hashes)
+        .map(|(q, hash)| match hash {
+            Some(hash) => q.short_id(needed(hash)),
+            None => q.short_id(0),
+        })
+        .collect()
+}
+
+// Looks for the quote an ID names, fed with the quotes of a pack one at a time.
+// An ID is an explicit `id` or a prefix of a content hash, never a position: those change
+// when the pack does.
+pub struct IdMatcher<'a> {
+    key: &'a str,
+    exact: Option<usize>,
+    // index and hash of the quotes whose hash starts with the key
+    prefixed: Vec<(usize, String)>,
+}
+
+impl<'a> IdMatcher<'a> {
+    pub fn new(key: &'a str) -> Self {
+        IdMatcher {
+            key,
+            exact: None,
+            prefixed: Vec::new(),
+        }
+    }
+
+    pub fn check(&mut self, index: usize, quote: &Quote) {
+        match &quote.id {
+            Some(id) if id == self.key && self.exact.is_none() => self.exact = Some(index),
+            Some(_) => {}
+            None => {
+                let hash = quote.hash_id();
+                if self.key.len() >= SHORT_ID_LEN && hash.starts_with(&self.key.to_lowercase()) {
+                    self.prefixed.push((index, hash));
+                }
+            }
+        }
+    }
+
+    // The index of the quote, `pack` naming it in errors.
+    pub fn finish(self, pack: &str) -> Result<usize, String> {
+        if let Some(index) = self.exact {
+            return Ok(index);
+        }
+        match self.prefixed.as_slice() {
+            [(index, _)] => return Ok(*index),
+            [] => {}
+            matches => {
+                // as long as it takes to tell the matches apart
+                let mut hashes: Vec<&str> = matches.iter().map(|(_, h)| h.as_str()).collect();
+                hashes.sort_unstable();
+                let len = (self.key.len() + 1..16)
+                    .find(|&len| hashes.windows(2).all(|w| w[0][..len] != w[1][..len]))
+                    .unwrap_or(16);
+                let candidates: Vec<String> = matches
+                    .iter()
+                    .take(5)
+                    .map(|(_, hash)| format!("{pack}:{}", &hash[..len]))
+                    .collect();
+                return Err(format!(
+                    "ambiguous quote ID '{pack}:{}', {} quotes match: {}{}",
+                    self.key,
+                    matches.len(),
+                    candidates.join(", "),
+                    if matches.len() > 5 { ", ..." } else { "" }
+                ));
+            }
+        }
+        if self.key.len() < SHORT_ID_LEN && self.key.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!(
+                "'{pack}:{}' is not a quote ID, positions change with the pack: see `kotofetch list` \
+                 for the IDs, or show a quote by position with --index",
+                self.key
+            ));
+        }
+        Err(format!("no quote found for '{pack}:{}'", self.key))
+    }
+}
+
+// Where a pack was found.
+#[derive(Debug, Clone)]
+pub enum PackSource {
+    File(PathBuf),
+    Builtin(&'static str, &'static str),
+    // the "-" mode
+    Stdin,
+}
+
+// Directory holding the user's quote files (~/.config/kotofetch/quotes).
+pub fn user_quotes_dir() -> PathBuf {
+    let mut path = dirs::config_dir().unwrap_or_default();
+    path.push("kotofetch/quotes");
+    path
+}
+
+// File name of a new pack, with the .toml extension added if missing.
+pub fn pack_file_name(mode_file: &Path) -> PathBuf {
+    let mut file_name = mode_file.to_path_buf();
+    if file_name.extension().is_none() {
+        file_name.set_extension("toml");
+    }
+    file_name
+}
+
+// File names a mode may refer to. A mode without extension matches a file in any known
+// format, tried in the order of `FORMATS` ("anime" -> anime.toml, anime.json, ...).
+pub fn pack_file_names(mode_file: &Path) -> Vec<PathBuf> {
+    if mode_file.extension().is_some() {
+        return vec![mode_file.to_path_buf()];
+    }
+    FORMATS
+        .iter()
+        .flat_map(|f| f.extensions)
+        .map(|ext| mode_file.with_extension(ext))
+        .collect()
+}
+
+// Pack name of a mode, i.e. its file name without extension ("anime").
+pub fn pack_name(mode_file: &Path) -> String {
+    mode_file
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// How a pack is named in quote IDs: its name, or the path it was given as.
+pub fn pack_id(mode_file: &Path) -> String {
+    if mode_file.components().count() > 1 {
+        mode_file.display().to_string()
+    } else {
+        pack_name(mode_file)
+    }
 }
 
-#[derive(Deserialize, Debug, Clone)]
-pub struct QuotesFile {
-    #[serde(rename = "quote")]
-    pub quotes: Vec<Quote>,
+// Directories searched for quote files, most important first:
+// the extra directories (--quotes-dir, then `quotes_dirs` from the config), the user
+// quotes directory, $XDG_DATA_HOME/kotofetch/quotes and each $XDG_DATA_DIRS/kotofetch/quotes.
+// Built-in quotes come after all of them.
+pub fn search_path(extra: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = extra.to_vec();
+    dirs.push(user_quotes_dir());
+    if let Some(data) = dirs::data_dir() {
+        dirs.push(data.join("kotofetch/quotes"));
+    }
+    if cfg!(unix) {
+        let data_dirs = std::env::var("XDG_DATA_DIRS")
+            .ok()
+            .filter(|d| !d.is_empty())
+            .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+        for d in data_dirs.split(':').filter(|d| !d.is_empty()) {
+            dirs.push(Path::new(d).join("kotofetch/quotes"));
+        }
+    }
+
+    let mut seen = Vec::new();
+    dirs.retain(|d| {
+        let new = !seen.contains(d);
+        seen.push(d.clone());
+        new
+    });
+    dirs
+}
+
+// Find a mode file.
+// "-" reads the pack from stdin and "@favorites" the favorites. A mode that is a path to an existing file is used as is, otherwise the first
+// match in the search path wins, then the built-in quotes.
+pub fn resolve_pack(mode_file: &Path, search: &[PathBuf]) -> Option<PackSource> {
+    if mode_file == Path::new("-") {
+        return Some(PackSource::Stdin);
+    }
+    if mode_file == Path::new(FAVORITES_MODE) {
+        let path = favorites_file();
+        return path.is_file().then_some(PackSource::File(path));
+    }
+    if mode_file.components().count() > 1 && mode_file.is_file() {
+        return Some(PackSource::File(mode_file.to_path_buf()));
+    }
+
+    let file_names = pack_file_names(mode_file);
+    for dir in search {
+        for file_name in &file_names {
+            let path = dir.join(file_name);
+            if path.is_file() {
+                return Some(PackSource::File(path));
+            }
+        }
+    }
+
+    let file_name = pack_file_name(mode_file);
+    let file_str = file_name.to_str().unwrap_or_default();
+    BUILTIN_QUOTES
+        .iter()
+        .find(|&&(name, _)| name == file_str)
+        .map(|&(name, content)| PackSource::Builtin(name, content))
+}
+
+// Every pack in the search path and the built-ins, by name, with the source that wins
+// followed by the ones it shadows.
+pub fn all_packs(search: &[PathBuf]) -> BTreeMap<String, Vec<PackSource>> {
+    let mut packs: BTreeMap<String, Vec<PackSource>> = BTreeMap::new();
+    for dir in search {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && format_of_path(p).is_some())
+            .collect();
+        // same order as resolve_pack when a pack exists in several formats
+        files.sort_by_key(|p| {
+            let rank = pack_file_names(Path::new(&pack_name(p)))
+                .iter()
+                .position(|n| n.file_name() == p.file_name());
+            (pack_name(p), rank)
+        });
+        for path in files {
+            packs
+                .entry(pack_name(&path))
+                .or_default()
+                .push(PackSource::File(path));
+        }
+    }
+    for &(name, content) in BUILTIN_QUOTES {
+        packs
+            .entry(pack_name(Path::new(name)))
+            .or_default()
+            .push(PackSource::Builtin(name, content));
+    }
+    packs
+}
+
+impl fmt::Display for PackSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackSource::File(path) => write!(f, "{}", path.display()),
+            PackSource::Builtin(name, _) => write!(f, "built-in {name}"),
+            PackSource::Stdin => write!(f, "stdin"),
+        }
+    }
+}
+
+// The command of a command pack, a TOML file like
+//   command = "~/bin/quotes --today"
+//   format = "json"   # optional, guessed from the output otherwise
+pub fn pack_command(src: &str) -> Option<(String, Option<String>)> {
+    let table = src.parse::<toml::Table>().ok()?;
+    let command = table.get("command")?.as_str()?.to_string();
+    let format = table
+        .get("format")
+        .and_then(|f| f.as_str())
+        .map(str::to_string);
+    Some((command, format))
+}
+
+// Run the command of a command pack from the pack's directory and return its output.
+fn run_pack_command(command: &str, dir: &Path) -> Result<String, String> {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = process::Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = process::Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    let output = cmd
+        .arg(command)
+        .current_dir(dir)
+        .stdin(process::Stdio::null())
+        .output()
+        .map_err(|e| format!("failed to run '{command}': {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut message = format!("'{command}' failed ({})", output.status);
+        if !stderr.trim().is_empty() {
+            message = format!("{message}: {}", stderr.trim());
+        }
+        return Err(message);
+    }
+    String::from_utf8(output.stdout).map_err(|_| format!("'{command}' printed invalid UTF-8"))
+}
+
+// Directories whose command packs may run, set once from the user config. Command
+// packs anywhere else are skipped, so a folder named by a project can't run anything.
+static COMMAND_DIRS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+pub fn allow_command_packs_in(dirs: &[PathBuf]) {
+    let dirs = dirs.iter().filter_map(|d| d.canonicalize().ok()).collect();
+    COMMAND_DIRS.set(dirs).ok();
+}
+
+fn command_allowed(dir: &Path) -> bool {
+    let dirs = COMMAND_DIRS.get().map_or(&[][..], Vec::as_slice);
+    dir.canonicalize().is_ok_and(|dir| dirs.contains(&dir))
+}
+
+// Parse the content of a pack file, running it first if it is a command pack.
+pub fn parse_pack_file(path: &Path, file: &str, src: &str) -> Loaded {
+    let format = detect_format(path, src);
+    if format.name != "TOML" {
+        return parse_pack(format, file, src);
+    }
+    let Some((command, output_format)) = pack_command(src) else {
+        return parse_pack(format, file, src);
+    };
+
+    let dir = path
+        .parent()
+        .filter(|d| d.is_dir())
+        .unwrap_or(Path::new("."));
+    if !command_allowed(dir) {
+        eprintln!(
+            "Warning: {file}: command pack not run, command packs only run from the quotes \
+             folder and the quotes_dirs of your config.toml, with allow_command_packs = true"
+        );
+        return Loaded {
+            quotes: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+    }
+    let label = format!("{file} ({command})");
+    let output = match run_pack_command(&command, dir) {
+        Ok(output) => output,
+        Err(e) => {
+            return Loaded {
+                quotes: Vec::new(),
+                diagnostics: vec![Diagnostic {
+                    file: file.to_string(),
+                    location: None,
+                    level: Level::Error,
+                    message: e,
+                }],
+            };
+        }
+    };
+    let format = output_format
+        .and_then(|f| format_by_name(&f))
+        .unwrap_or_else(|| sniff_format(&output));
+    parse_pack(format, &label, &output)
+}
+
+// Packs bigger than this are read a quote at a time instead of all at once.
+const STREAM_SIZE: u64 = 1 << 20;
+// How much of a pack file is looked at to tell its format.
+const HEAD_SIZE: usize = 64 * 1024;
+
+fn open_file(path: &Path) -> Result<(BufReader<fs::File>, String), String> {
+    let file =
+        fs::File::open(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let mut reader = BufReader::with_capacity(HEAD_SIZE, file);
+    let head = reader
+        .fill_buf()
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let head = String::from_utf8_lossy(head).into_owned();
+    Ok((reader, head))
+}
+
+// Whether the start of a pack file makes it a command pack. Top-level keys come
+// before any table, so the command is found without reading the quotes.
+fn is_command_head(path: &Path, head: &str) -> bool {
+    if detect_format(path, head).name != "TOML" {
+        return false;
+    }
+    let end = head
+        .match_indices("\n[[")
+        .next()
+        .map_or(head.len(), |(i, _)| i + 1);
+    pack_command(&head[..end]).is_some()
+}
+
+pub fn is_command_pack(path: &Path) -> bool {
+    open_file(path).is_ok_and(|(_, head)| is_command_head(path, &head))
+}
+
+// Go through the quotes of a pack file in order, without holding them all in memory.
+// `on_quote` returns false to stop. Command packs are run and their output parsed.
+pub fn stream_file(
+    path: &Path,
+    on_quote: &mut dyn FnMut(Quote, Location) -> bool,
+) -> Result<Vec<Diagnostic>, String> {
+    let (mut reader, head) = open_file(path)?;
+    let file = path.display().to_string();
+    if is_command_head(path, &head) {
+        let mut src = String::new();
+        reader
+            .read_to_string(&mut src)
+            .map_err(|e| format!("failed to read {file}: {e}"))?;
+        let loaded = parse_pack_file(path, &file, &src);
+        for (quote, location) in loaded.quotes {
+            if !on_quote(quote, location) {
+                break;
+            }
+        }
+        return Ok(loaded.diagnostics);
+    }
+    let format = detect_format(path, &head);
+    Ok(stream_pack(format, &file, &mut reader, on_quote))
+}
+
+// Read and parse a pack.
+pub fn load_source(source: &PackSource) -> Result<Loaded, String> {
+    match source {
+        PackSource::File(path) => {
+            let src = fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+            Ok(parse_pack_file(path, &path.display().to_string(), &src))
+        }
+        PackSource::Builtin(name, content) => Ok(parse_pack(
+            toml_format(),
+            &format!("<built-in>/{name}"),
+            content,
+        )),
+        PackSource::Stdin => {
+            let mut src = String::new();
+            io::stdin()
+                .read_to_string(&mut src)
+                .map_err(|e| format!("failed to read stdin: {e}"))?;
+            Ok(parse_pack(sniff_format(&src), "<stdin>", &src))
+        }
+    }
+}
+
+// Load the quotes of a single mode file.
+// Invalid entries are reported and skipped, the rest of the pack is still used.
+pub fn load_mode(mode_file: &Path, search: &[PathBuf]) -> Vec<Quote> {
+    let Some(source) = resolve_pack(mode_file, search) else {
+        if mode_file == Path::new(FAVORITES_MODE) {
+            eprintln!("Warning: no favorites yet, add some with `kotofetch fav`");
+            return Vec::new();
+        }
+        eprintln!(
+            "Warning: mode file not found in quotes directories or built-in: {}",
+            mode_file.display()
+        );
+        return Vec::new();
+    };
+    match load_source(&source) {
+        Ok(loaded) => {
+            loaded.errors().for_each(|d| d.print());
+            loaded.quotes.into_iter().map(|(q, _)| q).collect()
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            Vec::new()
+        }
+    }
+}
+
+// Quotes of several packs. Cached packs are only decoded when a quote is used and
+// streamed packs are read again up to the quote.
+pub enum Segment {
+    Quotes(Vec<Quote>),
+    Cached(PackIndex),
+    // path, number of quotes and their total weight if any has one
+    Streamed(PathBuf, usize, Option<f64>),
+}
+
+impl Segment {
+    fn len(&self) -> usize {
+        match self {
+            Segment::Quotes(quotes) => quotes.len(),
+            Segment::Cached(pack) => pack.len(),
+            Segment::Streamed(_, len, _) => *len,
+        }
+    }
+
+    // Total weight of the quotes, None if none of them has a weight.
+    fn weight(&self) -> Option<f64> {
+        match self {
+            Segment::Quotes(quotes) => quotes
+                .iter()
+                .any(|q| q.weight.is_some())
+                .then(|| quotes.iter().map(|q| q.weight.unwrap_or(1.0)).sum()),
+            Segment::Cached(pack) => pack.weight(),
+            Segment::Streamed(_, _, weight) => *weight,
+        }
+    }
+
+    // Index of the quote found at `point` along the quotes' weights, where each quote
+    // spans its weight (1 by default).
+    fn pick(&self, point: f64) -> Option<usize> {
+        let last = self.len().checked_sub(1)?;
+        if self.weight().is_none() {
+            return Some((point as usize).min(last));
+        }
+        let found = match self {
+            Segment::Quotes(quotes) => {
+                let mut total = 0.0;
+                quotes.iter().position(|q| {
+                    total += q.weight.unwrap_or(1.0);
+                    total > point
+                })
+            }
+            Segment::Cached(pack) => pack.pick(point),
+            Segment::Streamed(path, _, _) => {
+                let mut total = 0.0;
+                let mut index: usize = 0;
+                stream_file(path, &mut |quote, _| {
+                    total += quote.weight.unwrap_or(1.0);
+                    index += 1;
+                    total <= point
+                })
+                .ok()?;
+                index.checked_sub(1)
+            }
+        };
+        // rounding can leave the point just past the end
+        Some(found.unwrap_or(last).min(last))
+    }
+
+    // Go through the quotes in order.
+    fn scan(&self, f: &mut dyn FnMut(usize, &Quote)) {
+        match self {
+            Segment::Quotes(quotes) => quotes.iter().enumerate().for_each(|(i, q)| f(i, q)),
+            Segment::Cached(pack) => pack.scan(f),
+            Segment::Streamed(path, _, _) => {
+                let mut index = 0;
+                stream_file(path, &mut |quote, _| {
+                    f(index, &quote);
+                    index += 1;
+                    true
+                })
+                .ok();
+            }
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<Quote> {
+        match self {
+            Segment::Quotes(quotes) => quotes.get(index).cloned(),
+            Segment::Cached(pack) => pack.get(index),
+            Segment::Streamed(path, _, _) => {
+                let mut seen = 0;
+                let mut found = None;
+                stream_file(path, &mut |quote, _| {
+                    if seen == index {
+                        found = Some(quote);
+                    }
+                    seen += 1;
+                    found.is_none()
+                })
+                .ok()?;
+                found
+            }
+        }
+    }
+}
+
+pub struct Pool {
+    segments: Vec<Segment>,
+}
+
+impl Pool {
+    // A pool of quotes already loaded, a pack per list.
+    #[cfg(test)]
+    pub fn of(packs: Vec<Vec<Quote>>) -> Self {
+        Pool {
+            segments: packs.into_iter().map(Segment::Quotes).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(Segment::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<Quote> {
+        let (segment, index) = self.locate(index)?;
+        self.segments[segment].get(index)
+    }
+
+    // The index of the quote that `key` names in the pack at `pack` in the modes,
+    // `name` naming the pack in errors. See IdMatcher.
+    pub fn find(&self, pack: usize, key: &str, name: &str) -> Result<usize, String> {
+        let segment = self
+            .segments
+            .get(pack)
+            .ok_or_else(|| format!("no quote found for '{name}:{key}'"))?;
+        let mut matcher = IdMatcher::new(key);
+        segment.scan(&mut |index, quote| matcher.check(index, quote));
+        let start: usize = self.segments[..pack].iter().map(Segment::len).sum();
+        Ok(start + matcher.finish(name)?)
+    }
+
+    // Go through every quote in order with its global index and its pack.
+    pub fn scan(&self, f: &mut dyn FnMut(usize, usize, &Quote)) {
+        let mut start = 0;
+        for (pack, segment) in self.segments.iter().enumerate() {
+            segment.scan(&mut |index, quote| f(start + index, pack, quote));
+            start += segment.len();
+        }
+    }
+
+    // The pack (position in the modes) of a quote and its index in the pack.
+    pub fn locate(&self, mut index: usize) -> Option<(usize, usize)> {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if index < segment.len() {
+                return Some((i, index));
+            }
+            index -= segment.len();
+        }
+        None
+    }
+
+    // Index of a random quote. `pack_weights` holds the weight of each pack, in order.
+    // By default a quote's chance is its weight (1 if unset) times its pack's weight;
+    // `by_pack` picks a pack by its weight first, then one of its quotes by weight.
+    // Without any weight every quote is as likely, like a plain uniform pick.
+    pub fn choose(&self, rng: &mut impl Rng, pack_weights: &[f64], by_pack: bool) -> Option<usize> {
+        let pack_weight = |i: usize| pack_weights.get(i).copied().unwrap_or(1.0);
+        let weighted = pack_weights.iter().any(|w| *w != 1.0)
+            || self.segments.iter().any(|s| s.weight().is_some());
+        if !by_pack && !weighted {
+            return (0..self.len()).choose(rng);
+        }
+
+        // (index of the first quote, weight, pack weight, segment) of each pack with quotes
+        let mut start = 0;
+        let mut packs = Vec::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            if segment.len() > 0 {
+                let quotes = segment.weight().unwrap_or(segment.len() as f64);
+                let weight = if by_pack {
+                    pack_weight(i)
+                } else {
+                    pack_weight(i) * quotes
+                };
+                packs.push((start, weight, pack_weight(i), segment));
+            }
+            start += segment.len();
+        }
+        let total: f64 = packs.iter().map(|(_, w, _, _)| w).sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mut point = rng.random_range(0.0..total);
+        let (start, _, pack_weight, segment) = packs
+            .iter()
+            .find(|(_, weight, _, _)| {
+                let found = point < *weight;
+                if !found {
+                    point -= weight;
+                }
+                found
+            })
+            .or(packs.last())?;
+        let index = if by_pack {
+            match segment.weight() {
+                Some(quotes) => segment.pick(rng.random_range(0.0..quotes))?,
+                None => (0..segment.len()).choose(rng)?,
+            }
+        } else {
+            // back to the quotes' own weights
+            segment.pick(point / pack_weight)?
+        };
+        Some(start + index)
+    }
+}
+
+// Count the quotes of a big pack file, reporting its errors, so they can be streamed
+// later. Small files and command packs are simply loaded.
+fn stream_segment(path: &Path, mode: &Path, search: &[PathBuf]) -> Segment {
+    let big = fs::metadata(path).is_ok_and(|m| m.len() > STREAM_SIZE);
+    if !big || is_command_pack(path) {
+        return Segment::Quotes(load_mode(mode, search));
+    }
+    let mut len = 0;
+    let mut weight = 0.0;
+    let mut weighted = false;
+    match stream_file(path, &mut |quote, _| {
+        len += 1;
+        weight += quote.weight.unwrap_or(1.0);
+        weighted |= quote.weight.is_some();
+        true
+    }) {
+        Ok(diagnostics) => diagnostics
+            .iter()
+            .filter(|d| d.level == Level::Error)
+            .for_each(|d| d.print()),
+        Err(e) => eprintln!("{e}"),
+    }
+    Segment::Streamed(path.to_path_buf(), len, weighted.then_some(weight))
+}
+
+// Like load_pool, but quotes are only read when used. Pack files go through the pack
+// cache when `use_cache` is set and big ones are streamed otherwise.
+pub fn load_cached_pool(modes: &[PathBuf], search: &[PathBuf], use_cache: bool) -> Pool {
+    let segments = modes
+        .iter()
+        .map(|mode| match resolve_pack(mode, search) {
+            Some(PackSource::File(path)) => match use_cache.then(|| cache::open(&path)).flatten() {
+                Some(index) => Segment::Cached(index),
+                None => stream_segment(&path, mode, search),
+            },
+            _ => Segment::Quotes(load_mode(mode, search)),
+        })
+        .collect();
+    Pool { segments }
+}
+
+// Load and flatten the quotes of every mode file, in order.
+pub fn load_pool(modes: &[PathBuf], search: &[PathBuf]) -> Vec<Quote> {
+    modes.iter().flat_map(|m| load_mode(m, search)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::collections::HashMap;
+
+    const DRAWS: usize = 10_000;
+    // a ratio of 10k draws is this close to its expected value, very likely
+    const TOLERANCE: f64 = 0.02;
+
+    fn quote(japanese: &str, weight: Option<f64>) -> Quote {
+        Quote {
+            japanese: japanese.to_string(),
+            weight,
+            ..Quote::default()
+        }
+    }
+
+    // How often each pack comes up in DRAWS picks.
+    fn pack_shares(pool: &Pool, pack_weights: &[f64], by_pack: bool) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut counts = vec![0; pool.segments.len()];
+        for _ in 0..DRAWS {
+            let index = pool.choose(&mut rng, pack_weights, by_pack).unwrap();
+            counts[pool.locate(index).unwrap().0] += 1;
+        }
+        counts.iter().map(|&c| c as f64 / DRAWS as f64).collect()
+    }
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < TOLERANCE,
+            "{actual} isn't close to {expected}"
+        );
+    }
+
+    // The quote `key` names among `quotes`, through IdMatcher.
+    fn find(quotes: &[Quote], key: &str) -> Result<usize, String> {
+        let mut matcher = IdMatcher::new(key);
+        for (i, quote) in quotes.iter().enumerate() {
+            matcher.check(i, quote);
+        }
+        matcher.finish("pack")
+    }
+
+    #[test]
+    fn id_matcher_prefixes() {
+        let mut named = quote("名前", None);
+        named.id = Some("name".to_string());
+        let quotes = [quote("犬", None), named, quote("猫", None)];
+        let hash = quotes[2].hash_id();
+
+        assert_eq!(find(&quotes, "name"), Ok(1));
+        assert_eq!(find(&quotes, &hash), Ok(2));
+        assert_eq!(find(&quotes, &hash[..SHORT_ID_LEN]), Ok(2));
+        assert_eq!(find(&quotes, &hash[..6].to_uppercase()), Ok(2));
+        // too short for a prefix, a number isn't taken as a position
+        let error = find(&quotes, "0").unwrap_err();
+        assert!(
+            error.contains("not a quote ID") && error.contains("--index"),
+            "{error}"
+        );
+        assert!(find(&quotes, "3").is_err());
+        assert!(find(&quotes, "ffff0000ffff").is_err());
+    }
+
+    #[test]
+    fn id_matcher_ambiguous_prefix() {
+        // two quotes whose hashes start alike
+        let mut seen: HashMap<String, Quote> = HashMap::new();
+        let (first, second) = (0..)
+            .map(|i| quote(&format!("句{i}"), None))
+            .find_map(|q| {
+                let prefix = q.hash_id()[..SHORT_ID_LEN].to_string();
+                match seen.get(&prefix) {
+                    Some(first) => Some((first.clone(), q)),
+                    None => {
+                        seen.insert(prefix, q);
+                        None
+                    }
+                }
+            })
+            .unwrap();
+        let quotes = [first, second];
+        let (a, b) = (quotes[0].hash_id(), quotes[1].hash_id());
+
+        let error = find(&quotes, &a[..SHORT_ID_LEN]).unwrap_err();
+        assert!(error.starts_with("ambiguous quote ID"), "{error}");
+        // the candidates are as long as it takes to tell them apart
+        let len = (0..16)
+            .find(|&i| a.as_bytes()[i] != b.as_bytes()[i])
+            .unwrap()
+            + 1;
+        assert!(
+            error.contains(&format!("pack:{}", &a[..len.max(5)])),
+            "{error}"
+        );
+        assert_eq!(find(&quotes, &a), Ok(0));
+    }
+
+    #[test]
+    fn pack_weights_by_pack() {
+        // sizes don't matter when picking a pack first
+        let pool = Pool::of(vec![
+            vec![quote("一", None), quote("二", None)],
+            (0..5).map(|i| quote(&i.to_string(), None)).collect(),
+        ]);
+        let shares = pack_shares(&pool, &[1.0, 3.0], true);
+        assert_near(shares[0], 0.25);
+        assert_near(shares[1], 0.75);
+    }
+
+    #[test]
+    fn pack_weights_by_quote() {
+        // a quote's chance is its weight times its pack's: 1 × 1 against 2 × 3 + 1 × 3
+        let pool = Pool::of(vec![
+            vec![quote("一", None)],
+            vec![quote("二", Some(2.0)), quote("三", None)],
+        ]);
+        let shares = pack_shares(&pool, &[1.0, 3.0], false);
+        assert_near(shares[0], 0.1);
+        assert_near(shares[1], 0.9);
+    }
+
+    #[test]
+    fn quote_weights_within_a_pack() {
+        let pool = Pool::of(vec![vec![quote("一", Some(1.0)), quote("二", Some(3.0))]]);
+        let mut rng = StdRng::seed_from_u64(7);
+        let second = (0..DRAWS)
+            .filter(|_| pool.choose(&mut rng, &[1.0], true) == Some(1))
+            .count();
+        assert_near(second as f64 / DRAWS as f64, 0.75);
+    }
 }
@@ -0,0 +1,343 @@
+// `kotofetch render`: the boxed quote drawn as a PNG picture, for wallpapers and lock
+// screens. The card is laid out like the SVG one (see graphics.rs), scaled to the
+// picture, and the text is drawn with the font given or a Japanese system font.
+use crate::cli::{CardLayout, Cli, RenderArgs};
+use crate::config::RuntimeConfig;
+use crate::display::{Layout, Part, choose_quote, color_to_hex};
+use crate::motd::write_atomic;
+use crate::output::part_color;
+use crate::quotes::Quote;
+use ab_glyph::{Font, FontVec, PxScale, PxScaleFont, ScaleFont, point};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Where the usual Japanese fonts are installed, tried in order without --font.
+const SYSTEM_FONTS: &[&str] = &[
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc", // Debian, Ubuntu
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",      // Arch
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc", // Fedora
+    "/usr/share/fonts/opentype/noto/NotoSerifCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSerifCJK-Regular.ttc",
+    "/System/Library/Fonts/ヒラギノ角ゴシック W3.ttc", // macOS
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "C:\\Windows\\Fonts\\YuGothM.ttc",
+    "C:\\Windows\\Fonts\\msgothic.ttc",
+];
+
+// Share of the picture the card may take.
+const FILL: f32 = 0.8;
+
+type Rgb = [f32; 3];
+type Scaled<'a> = PxScaleFont<&'a FontVec>;
+
+pub fn run(runtime: &RuntimeConfig, cli: &Cli, args: &RenderArgs) -> Result<(), String> {
+    let font = load_font(args.font.as_deref())?;
+    let quote = choose_quote(runtime, cli)?;
+    let png = render(runtime, &quote, &font, args)?;
+    write_atomic(&args.png, &png)?;
+    eprintln!("Rendered {}", args.png.display());
+    Ok(())
+}
+
+// The quote drawn on its card, encoded as a PNG.
+fn render(
+    runtime: &RuntimeConfig,
+    quote: &Quote,
+    font: &FontVec,
+    args: &RenderArgs,
+) -> Result<Vec<u8>, String> {
+    // the layout decides how the text is aligned
+    let mut runtime = runtime.clone();
+    runtime.centered = args.layout == CardLayout::Centered;
+    let layout = Layout::new(&runtime, quote);
+
+    let (width, height) = args.size;
+    let mut canvas = Canvas::new(width, height);
+    let top = rgb(&args.background);
+    let bottom = args.gradient.as_deref().map_or(top, rgb);
+    canvas.gradient(top, bottom);
+    draw_card(&mut canvas, &runtime, &layout, font, args.layout);
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&canvas.to_bytes()))
+        .map_err(|e| format!("failed to encode the picture: {e}"))?;
+    Ok(png)
+}
+
+fn load_font(path: Option<&Path>) -> Result<FontVec, String> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => SYSTEM_FONTS
+            .iter()
+            .map(PathBuf::from)
+            .find(|p| p.exists())
+            .ok_or("no Japanese font found, give one with --font")?,
+    };
+    let data = fs::read(&path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    // the first font of a collection, the Japanese one for Noto CJK
+    FontVec::try_from_vec_and_index(data, 0)
+        .map_err(|e| format!("failed to read font {}: {e}", path.display()))
+}
+
+fn rgb(color: &str) -> Rgb {
+    let hex = color_to_hex(color).unwrap_or("#ffffff".to_string());
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(255) as f32 / 255.0;
+    [channel(1), channel(3), channel(5)]
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<Rgb>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        Canvas {
+            width,
+            height,
+            pixels: vec![[0.0; 3]; width as usize * height as usize],
+        }
+    }
+
+    // Mix `color` over the pixel, `alpha` of it.
+    fn blend(&mut self, x: u32, y: u32, color: Rgb, alpha: f32) {
+        if x >= self.width || y >= self.height || alpha <= 0.0 {
+            return;
+        }
+        let alpha = alpha.min(1.0);
+        let pixel = &mut self.pixels[(y * self.width + x) as usize];
+        for (p, c) in pixel.iter_mut().zip(color) {
+            *p += (c - *p) * alpha;
+        }
+    }
+
+    // From `top` to `bottom`, line by line.
+    fn gradient(&mut self, top: Rgb, bottom: Rgb) {
+        for y in 0..self.height {
+            let t = y as f32 / (self.height - 1).max(1) as f32;
+            let color = [0, 1, 2].map(|i| top[i] + (bottom[i] - top[i]) * t);
+            for x in 0..self.width {
+                self.pixels[(y * self.width + x) as usize] = color;
+            }
+        }
+    }
+
+    // A rectangle with rounded corners: the inside darkened and, unless `stroke` is 0, the
+    // outline in `color`, both antialiased.
+    fn card(&mut self, (x, y, w, h): (f32, f32, f32, f32), radius: f32, stroke: f32, color: Rgb) {
+        let (cx, cy) = (x + w / 2.0, y + h / 2.0);
+        let (hw, hh) = (w / 2.0, h / 2.0);
+        let margin = stroke.ceil() as i64 + 1;
+        let x0 = (x.floor() as i64 - margin).max(0) as u32;
+        let y0 = (y.floor() as i64 - margin).max(0) as u32;
+        let x1 = ((x + w).ceil() as i64 + margin).max(0) as u32;
+        let y1 = ((y + h).ceil() as i64 + margin).max(0) as u32;
+        for py in y0..y1.min(self.height) {
+            for px in x0..x1.min(self.width) {
+                // signed distance from the pixel center to the outline
+                let qx = (px as f32 + 0.5 - cx).abs() - hw + radius;
+                let qy = (py as f32 + 0.5 - cy).abs() - hh + radius;
+                let outside = qx.max(0.0).hypot(qy.max(0.0));
+                let distance = outside + qx.max(qy).min(0.0) - radius;
+                self.blend(px, py, [0.0; 3], 0.25 * (0.5 - distance).clamp(0.0, 1.0));
+                if stroke > 0.0 {
+                    let coverage = (stroke / 2.0 - distance.abs() + 0.5).clamp(0.0, 1.0);
+                    self.blend(px, py, color, coverage);
+                }
+            }
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flatten()
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect()
+    }
+}
+
+// Width of a line of text at `scale`, in pixels.
+fn text_width(font: &Scaled, text: &str) -> f32 {
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+// Draw a line of text from `x`, on the baseline `y`. Bold is drawn twice, a little apart.
+fn draw_text(
+    canvas: &mut Canvas,
+    font: &Scaled,
+    text: &str,
+    (x, y): (f32, f32),
+    color: Rgb,
+    bold: bool,
+) {
+    let offsets: &[f32] = if bold {
+        &[0.0, font.scale().y / 28.0]
+    } else {
+        &[0.0]
+    };
+    for offset in offsets {
+        let mut caret = x + offset;
+        let mut previous = None;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                caret += font.kern(previous, id);
+            }
+            let glyph = id.with_scale_and_position(font.scale(), point(caret, y));
+            caret += font.h_advance(id);
+            previous = Some(id);
+            if let Some(outline) = font.outline_glyph(glyph) {
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, coverage| {
+                    let px = bounds.min.x as i64 + gx as i64;
+                    let py = bounds.min.y as i64 + gy as i64;
+                    if px >= 0 && py >= 0 {
+                        canvas.blend(px as u32, py as u32, color, coverage);
+                    }
+                });
+            }
+        }
+    }
+}
+
+// The box of the terminal as a card: a column is half an em, a line one and a half, the
+// border runs through the middle of the outer ones.
+fn draw_card(
+    canvas: &mut Canvas,
+    runtime: &RuntimeConfig,
+    layout: &Layout,
+    font: &FontVec,
+    placement: CardLayout,
+) {
+    // centered rows are centered again on the card, the others keep their indentation
+    let rows: Vec<Option<(Part, String)>> = layout
+        .rows(layout.inner_width(None))
+        .into_iter()
+        .map(|row| {
+            row.map(|(part, content)| match runtime.centered {
+                true => (part, content.trim().to_string()),
+                false => (part, content.trim_end().to_string()),
+            })
+        })
+        .collect();
+
+    // everything scales with the em, measured at 1px first
+    let units = font.units_per_em().unwrap_or(1000.0);
+    let height_per_em = font.height_unscaled() / units;
+    let unit = font.as_scaled(PxScale::from(height_per_em));
+    let border = if runtime.border { 1.0 } else { 0.0 };
+    let side = (runtime.horizontal_padding as f32 + border) / 2.0;
+    let inner = rows
+        .iter()
+        .flatten()
+        .map(|(_, text)| text_width(&unit, text))
+        .fold(0.0, f32::max);
+    let card_width = inner + 2.0 * side;
+    let card_height = 1.5 * (rows.len() as f32 + 2.0 * border);
+
+    let (width, height) = (canvas.width as f32, canvas.height as f32);
+    let left = width / 12.0;
+    let room = match placement {
+        CardLayout::Centered => width * FILL,
+        CardLayout::Left => (width - 2.0 * left) * FILL,
+    };
+    let em = (room / card_width)
+        .min(height * FILL / card_height)
+        .min(height / 14.0);
+    let scaled = font.as_scaled(PxScale::from(height_per_em * em));
+    let (card_width, card_height) = (card_width * em, card_height * em);
+    let x0 = match placement {
+        CardLayout::Centered => (width - card_width) / 2.0,
+        CardLayout::Left => left,
+    };
+    let y0 = (height - card_height) / 2.0;
+    let (column, line) = (em / 2.0, em * 1.5);
+
+    // the card is filled either way, only its outline follows the border setting
+    let outline = (
+        x0 + column / 2.0,
+        y0 + line / 2.0,
+        card_width - column,
+        card_height - line,
+    );
+    let radius = if runtime.rounded_border { column } else { 0.0 };
+    let stroke = if runtime.border {
+        (em / 12.0).max(1.0)
+    } else {
+        0.0
+    };
+    canvas.card(outline, radius, stroke, rgb(&runtime.border_color));
+
+    let baseline = (scaled.ascent() + scaled.descent()) / 2.0;
+    for (i, row) in rows.iter().enumerate() {
+        let Some((part, text)) = row else {
+            continue;
+        };
+        let y = y0 + (border + i as f32 + 0.5) * line + baseline;
+        let x = if runtime.centered {
+            x0 + (card_width - text_width(&scaled, text)) / 2.0
+        } else {
+            x0 + side * em
+        };
+        let color = part_color(runtime, *part).map_or([1.0; 3], |c| rgb(&c));
+        let bold = *part == Part::Japanese && runtime.bold;
+        draw_text(canvas, &scaled, text, (x, y), color, bold);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_a_small_png() {
+        // any font with Latin letters will do for a Latin quote
+        let Ok(font) = load_font(Some(Path::new(
+            "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+        ))) else {
+            return;
+        };
+        let quote = Quote {
+            japanese: "Hello".to_string(),
+            translation: Some("world".to_string()),
+            ..Quote::default()
+        };
+        let args = RenderArgs {
+            png: PathBuf::new(),
+            font: None,
+            size: (64, 48),
+            background: "#102030".to_string(),
+            gradient: None,
+            layout: CardLayout::Centered,
+        };
+        let png = render(&RuntimeConfig::default(), &quote, &font, &args).unwrap();
+
+        let mut reader = png::Decoder::new(std::io::Cursor::new(png))
+            .read_info()
+            .unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (64, 48));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+        // the corner is background, the card and the text are drawn over the middle
+        assert_eq!(pixels[..3], [0x10, 0x20, 0x30]);
+        assert!(pixels.chunks(3).any(|p| p != [0x10, 0x20, 0x30]));
+    }
+}
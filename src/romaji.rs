@@ -0,0 +1,354 @@
+// Romaji helpers shared by the quiz and the pack checker.
+
+// Normalize romaji so that "shūryō", "shuuryou" and "Shu ryo" compare equal.
+// Macrons are folded to plain vowels, long vowels are collapsed and anything
+// that isn't a letter or digit (spaces, apostrophes, punctuation) is dropped.
+pub fn normalize_romaji(s: &str) -> String {
+    let folded: String = s
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            'ā' | 'â' => Some('a'),
+            'ī' | 'î' => Some('i'),
+            'ū' | 'û' => Some('u'),
+            'ē' | 'ê' => Some('e'),
+            'ō' | 'ô' => Some('o'),
+            c if c.is_ascii_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect();
+    folded
+        .replace("ou", "o")
+        .replace("oo", "o")
+        .replace("uu", "u")
+        .replace("aa", "a")
+        .replace("ii", "i")
+        .replace("ee", "e")
+}
+
+// One unit of kana, with every accepted spelling (Hepburn first).
+enum Token {
+    Kana(Vec<String>),
+    // あいうえお, which may also just lengthen the previous vowel
+    Vowel(char),
+    // っ, doubles the next consonant
+    Sokuon,
+    // ー
+    Long,
+}
+
+fn to_hiragana(c: char) -> char {
+    match c {
+        'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn mora(c: char) -> Option<&'static [&'static str]> {
+    let alts: &'static [&'static str] = match c {
+        'か' => &["ka"],
+        'き' => &["ki"],
+        'く' => &["ku"],
+        'け' => &["ke"],
+        'こ' => &["ko"],
+        'が' => &["ga"],
+        'ぎ' => &["gi"],
+        'ぐ' => &["gu"],
+        'げ' => &["ge"],
+        'ご' => &["go"],
+        'さ' => &["sa"],
+        'し' => &["shi", "si"],
+        'す' => &["su"],
+        'せ' => &["se"],
+        'そ' => &["so"],
+        'ざ' => &["za"],
+        'じ' => &["ji", "zi"],
+        'ず' => &["zu"],
+        'ぜ' => &["ze"],
+        'ぞ' => &["zo"],
+        'た' => &["ta"],
+        'ち' => &["chi", "ti"],
+        'つ' => &["tsu", "tu"],
+        'て' => &["te"],
+        'と' => &["to"],
+        'だ' => &["da"],
+        'ぢ' => &["ji", "di"],
+        'づ' => &["zu", "du"],
+        'で' => &["de"],
+        'ど' => &["do"],
+        'な' => &["na"],
+        'に' => &["ni"],
+        'ぬ' => &["nu"],
+        'ね' => &["ne"],
+        'の' => &["no"],
+        'は' => &["ha", "wa"],
+        'ひ' => &["hi"],
+        'ふ' => &["fu", "hu"],
+        'へ' => &["he", "e"],
+        'ほ' => &["ho"],
+        'ば' => &["ba"],
+        'び' => &["bi"],
+        'ぶ' => &["bu"],
+        'べ' => &["be"],
+        'ぼ' => &["bo"],
+        'ぱ' => &["pa"],
+        'ぴ' => &["pi"],
+        'ぷ' => &["pu"],
+        'ぺ' => &["pe"],
+        'ぽ' => &["po"],
+        'ま' => &["ma"],
+        'み' => &["mi"],
+        'む' => &["mu"],
+        'め' => &["me"],
+        'も' => &["mo"],
+        'や' | 'ゃ' => &["ya"],
+        'ゆ' | 'ゅ' => &["yu"],
+        'よ' | 'ょ' => &["yo"],
+        'ら' => &["ra"],
+        'り' => &["ri"],
+        'る' => &["ru"],
+        'れ' => &["re"],
+        'ろ' => &["ro"],
+        'わ' | 'ゎ' => &["wa"],
+        'ゐ' => &["i", "wi"],
+        'ゑ' => &["e", "we"],
+        'を' => &["o", "wo"],
+        'ん' => &["n", "m", "nn"],
+        'ゔ' => &["vu"],
+        _ => return None,
+    };
+    Some(alts)
+}
+
+fn vowel(c: char) -> Option<char> {
+    match c {
+        'あ' | 'ぁ' => Some('a'),
+        'い' | 'ぃ' => Some('i'),
+        'う' | 'ぅ' => Some('u'),
+        'え' | 'ぇ' => Some('e'),
+        'お' | 'ぉ' => Some('o'),
+        _ => None,
+    }
+}
+
+// Spellings of a kana followed by a small kana (きゃ, しょ, ふぁ, ...).
+fn digraph(first: char, small: char) -> Option<Vec<String>> {
+    let ending = match small {
+        'ゃ' => "a",
+        'ゅ' => "u",
+        'ょ' => "o",
+        'ぁ' => "a",
+        'ぃ' => "i",
+        'ぇ' => "e",
+        'ぉ' => "o",
+        _ => return None,
+    };
+    let yoon = matches!(small, 'ゃ' | 'ゅ' | 'ょ');
+
+    let heads: &[&str] = match (first, yoon) {
+        ('き', true) => &["ky"],
+        ('ぎ', true) => &["gy"],
+        ('に', true) => &["ny"],
+        ('ひ', true) => &["hy"],
+        ('び', true) => &["by"],
+        ('ぴ', true) => &["py"],
+        ('み', true) => &["my"],
+        ('り', true) => &["ry"],
+        ('し', true) => &["sh", "sy"],
+        ('じ', true) => &["j", "zy", "jy"],
+        ('ち', true) => &["ch", "ty", "cy"],
+        ('ぢ', true) => &["j", "dy"],
+        ('ふ', false) => &["f"],
+        ('ゔ', false) => &["v"],
+        ('う', false) => &["w"],
+        ('て', false) => &["t"],
+        ('で', false) => &["d"],
+        ('し', false) if ending == "e" => &["sh"],
+        ('じ', false) if ending == "e" => &["j"],
+        ('ち', false) if ending == "e" => &["ch"],
+        _ => return None,
+    };
+    Some(heads.iter().map(|h| format!("{h}{ending}")).collect())
+}
+
+fn tokenize(japanese: &str) -> Option<Vec<Token>> {
+    let chars: Vec<char> = japanese.chars().map(to_hiragana).collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if let Some(&next) = chars.get(i + 1)
+            && let Some(alts) = digraph(c, next)
+        {
+            tokens.push(Token::Kana(alts));
+            i += 2;
+            continue;
+        }
+
+        if c == 'っ' {
+            tokens.push(Token::Sokuon);
+        } else if c == 'ー' {
+            tokens.push(Token::Long);
+        } else if let Some(v) = vowel(c) {
+            tokens.push(Token::Vowel(v));
+        } else if let Some(alts) = mora(c) {
+            tokens.push(Token::Kana(alts.iter().map(|s| s.to_string()).collect()));
+        } else if c.is_alphanumeric() {
+            // kanji, latin letters, ...: the reading can't be derived
+            return None;
+        }
+        // punctuation and spaces are skipped
+        i += 1;
+    }
+    Some(tokens)
+}
+
+// Hepburn romaji of a kana only text, like "かんじ" -> "kanji".
+// Returns None when the text contains kanji or anything else that can't be read.
+pub fn kana_to_romaji(kana: &str) -> Option<String> {
+    let words = kana
+        .split_whitespace()
+        .map(|word| {
+            let mut out = String::new();
+            let mut double_next = false;
+            let mut after_n = false;
+            for token in tokenize(word)? {
+                let syllabic_n = matches!(&token, Token::Kana(alts) if alts[0] == "n");
+                let spelling = match token {
+                    Token::Kana(alts) => alts[0].clone(),
+                    Token::Vowel(v) => v.to_string(),
+                    Token::Sokuon => {
+                        double_next = true;
+                        continue;
+                    }
+                    Token::Long => out.chars().last().map(String::from).unwrap_or_default(),
+                };
+                if double_next {
+                    double_next = false;
+                    match spelling.as_bytes().first() {
+                        Some(b'c') => out.push('t'),
+                        Some(&c) if !b"aiueo".contains(&c) => out.push(c as char),
+                        _ => {}
+                    }
+                }
+                // ん before a vowel or y is written n' (きんえん -> kin'en, not kinen)
+                if after_n && spelling.starts_with(['a', 'i', 'u', 'e', 'o', 'y']) {
+                    out.push('\'');
+                }
+                after_n = syllabic_n;
+                out.push_str(&spelling);
+            }
+            Some(out)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(words.join(" "))
+}
+
+// Does `romaji` spell out `japanese`?
+// Returns None when the Japanese text isn't kana only and can't be checked.
+pub fn romaji_matches_kana(japanese: &str, romaji: &str) -> Option<bool> {
+    let tokens = tokenize(japanese)?;
+    let target = normalize_romaji(romaji).into_bytes();
+    let len = target.len();
+
+    // reachable[p]: the tokens so far can spell target[..p]
+    let mut reachable = vec![false; len + 1];
+    reachable[0] = true;
+    for (t, token) in tokens.iter().enumerate() {
+        let mut next = vec![false; len + 1];
+        for pos in (0..=len).filter(|&p| reachable[p]) {
+            let prev = pos.checked_sub(1).map(|p| target[p]);
+            let rest = &target[pos..];
+            match token {
+                Token::Kana(alts) => {
+                    for alt in alts {
+                        if rest.starts_with(alt.as_bytes()) {
+                            next[pos + alt.len()] = true;
+                        }
+                    }
+                }
+                Token::Vowel(v) => {
+                    if rest.first() == Some(&(*v as u8)) {
+                        next[pos + 1] = true;
+                    }
+                    // long vowels were collapsed by normalize_romaji
+                    let lengthens = match v {
+                        'u' => matches!(prev, Some(b'o' | b'u')),
+                        'i' => matches!(prev, Some(b'i' | b'e')),
+                        _ => prev == Some(*v as u8),
+                    };
+                    if lengthens {
+                        next[pos] = true;
+                    }
+                }
+                Token::Sokuon => {
+                    let doubled = match rest {
+                        [b't', b'c', b'h', ..] | [b'c', b'c', b'h', ..] => true,
+                        [a, b, ..] => a == b && !b"aiueo".contains(a),
+                        _ => false,
+                    };
+                    if doubled {
+                        next[pos + 1] = true;
+                    } else if t == tokens.len() - 1 {
+                        // trailing っ (あっ) is usually not written
+                        next[pos] = true;
+                    }
+                }
+                Token::Long => {
+                    if matches!(prev, Some(b'a' | b'i' | b'u' | b'e' | b'o')) {
+                        next[pos] = true;
+                    }
+                }
+            }
+        }
+        reachable = next;
+    }
+    Some(reachable[len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kana_to_hepburn() {
+        assert_eq!(kana_to_romaji("かんじ").as_deref(), Some("kanji"));
+        assert_eq!(kana_to_romaji("カタカナ").as_deref(), Some("katakana"));
+        assert_eq!(kana_to_romaji("しゃしん").as_deref(), Some("shashin"));
+        assert_eq!(kana_to_romaji("ねこ いぬ").as_deref(), Some("neko inu"));
+        assert_eq!(kana_to_romaji("漢字"), None);
+    }
+
+    #[test]
+    fn small_tsu_doubles_the_consonant() {
+        assert_eq!(kana_to_romaji("きって").as_deref(), Some("kitte"));
+        assert_eq!(kana_to_romaji("まっちゃ").as_deref(), Some("matcha"));
+        assert_eq!(kana_to_romaji("あっ").as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn long_vowels() {
+        assert_eq!(kana_to_romaji("ラーメン").as_deref(), Some("raamen"));
+        assert_eq!(kana_to_romaji("とうきょう").as_deref(), Some("toukyou"));
+        assert_eq!(normalize_romaji("Tōkyō"), normalize_romaji("toukyou"));
+        assert_eq!(normalize_romaji("shūryō"), normalize_romaji("Shu ryo"));
+        assert_eq!(romaji_matches_kana("とうきょう", "Tōkyō"), Some(true));
+        assert_eq!(romaji_matches_kana("ラーメン", "ramen"), Some(true));
+    }
+
+    #[test]
+    fn syllabic_n_before_a_vowel() {
+        assert_eq!(kana_to_romaji("きんえん").as_deref(), Some("kin'en"));
+        assert_eq!(kana_to_romaji("きねん").as_deref(), Some("kinen"));
+        assert_eq!(kana_to_romaji("ほんや").as_deref(), Some("hon'ya"));
+        assert_eq!(kana_to_romaji("しんぶん").as_deref(), Some("shinbun"));
+        assert_eq!(romaji_matches_kana("きんえん", "kin'en"), Some(true));
+    }
+
+    #[test]
+    fn wrong_reading_is_caught() {
+        assert_eq!(romaji_matches_kana("ねこ", "inu"), Some(false));
+        assert_eq!(romaji_matches_kana("きって", "kite"), Some(false));
+        assert_eq!(romaji_matches_kana("猫", "neko"), None);
+    }
+}
@@ -0,0 +1,323 @@
+// What kotofetch remembers between runs, in the state directory:
+//   last            ID of the quote shown last, for --last
+//   hidden          IDs of the quotes never to show again, one per line followed by the
+//                   beginning of the quote for humans
+//   favorites.toml  copies of the favorite quotes, a pack read through the @favorites mode
+//   tmp/            scratch files of `edit` and `import`, only readable by the user
+// IDs are written in full (explicit ID or whole content hash) so they stay unique.
+use crate::cli::MarkArgs;
+use crate::config::RuntimeConfig;
+use crate::display::truncate_to_width;
+use crate::manage::{find_table, parse_id, quote_table, read_document, read_pack, write_document};
+use crate::quotes::{IdMatcher, Quote, pack_id, short_ids};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml_edit::{ArrayOfTables, DocumentMut, Item};
+
+// The mode reading the favorites.
+pub const FAVORITES_MODE: &str = "@favorites";
+
+// Directory holding the state ($KOTOFETCH_STATE_DIR or ~/.local/state/kotofetch).
+pub fn state_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("KOTOFETCH_STATE_DIR").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    let mut path = dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .unwrap_or_else(std::env::temp_dir);
+    path.push("kotofetch");
+    path
+}
+
+// A new scratch file named like "{prefix}-{random}.{extension}" in the private tmp folder
+// of the state directory. It is created, never opened if it exists, so it can't be a
+// link planted by someone else; a name that is taken is drawn again.
+pub fn scratch_file(prefix: &str, extension: &str) -> Result<(PathBuf, fs::File), String> {
+    let dir = state_dir().join("tmp");
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(&dir)
+        .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+
+    for _ in 0..100 {
+        let path = dir.join(format!(
+            "{prefix}-{:016x}.{extension}",
+            rand::random::<u64>()
+        ));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("failed to create {}: {e}", path.display())),
+        }
+    }
+    Err(format!(
+        "failed to create a scratch file in {}",
+        dir.display()
+    ))
+}
+
+pub fn favorites_file() -> PathBuf {
+    state_dir().join("favorites.toml")
+}
+
+fn write_state(name: &str, content: &str) -> Result<(), String> {
+    let dir = state_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    let path = dir.join(name);
+    fs::write(&path, content).map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+// The ID a quote is remembered by, like "anime:3f9a0c1e22aa77bd".
+fn full_id(pack: &Path, quote: &Quote) -> String {
+    format!("{}:{}", pack_id(pack), quote.short_id(16))
+}
+
+// Remember the quote just shown. Failing to is not worth an error.
+pub fn set_last(pack: &Path, quote: &Quote) {
+    write_state("last", &format!("{}\n", full_id(pack, quote))).ok();
+}
+
+fn last() -> Result<String, String> {
+    fs::read_to_string(state_dir().join("last"))
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| "no quote was shown yet".to_string())
+}
+
+// Quotes never to show again.
+pub struct Hidden {
+    ids: HashSet<String>,
+    // the quote part of the IDs: favorites are copies of quotes from other packs, with
+    // the same content hash (or `id`), so they are matched on that alone
+    keys: HashSet<String>,
+    favorite_keys: HashSet<String>,
+}
+
+impl Hidden {
+    pub fn load() -> Self {
+        let content = fs::read_to_string(state_dir().join("hidden")).unwrap_or_default();
+        Self::parse(&content)
+    }
+
+    fn parse(content: &str) -> Self {
+        let ids: HashSet<String> = hidden_ids(content).map(str::to_string).collect();
+        let key_of = |id: &String| id.rsplit_once(':').map(|(_, key)| key.to_string());
+        let keys = ids.iter().filter_map(key_of).collect();
+        let favorite = format!("{FAVORITES_MODE}:");
+        let favorite_keys = ids
+            .iter()
+            .filter(|id| id.starts_with(&favorite))
+            .filter_map(key_of)
+            .collect();
+        Hidden {
+            ids,
+            keys,
+            favorite_keys,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn contains(&self, pack: &Path, quote: &Quote) -> bool {
+        let key = quote.short_id(16);
+        if pack == Path::new(FAVORITES_MODE) {
+            self.keys.contains(&key)
+        } else {
+            self.ids.contains(&full_id(pack, quote)) || self.favorite_keys.contains(&key)
+        }
+    }
+}
+
+fn hidden_ids(content: &str) -> impl Iterator<Item = &str> {
+    content.lines().filter_map(|l| l.split_whitespace().next())
+}
+
+// The quote an ID or --last names, its pack and the ID to show, as short as `list` has it.
+fn marked_quote(
+    runtime: &RuntimeConfig,
+    args: &MarkArgs,
+) -> Result<(PathBuf, Quote, String), String> {
+    let id = match &args.id {
+        Some(id) => id.clone(),
+        None => last()?,
+    };
+    let (pack, key) = parse_id(&id)?;
+    let (_, mut quotes) = read_pack(&pack, &runtime.quote_search_path())?;
+    let mut matcher = IdMatcher::new(&key);
+    quotes
+        .iter()
+        .enumerate()
+        .for_each(|(i, q)| matcher.check(i, q));
+    let index = matcher.finish(&pack_id(&pack))?;
+    let shown = format!("{}:{}", pack_id(&pack), short_ids(&quotes)[index]);
+    Ok((pack, quotes.swap_remove(index), shown))
+}
+
+fn read_favorites() -> Result<DocumentMut, String> {
+    let path = favorites_file();
+    if path.exists() {
+        read_document(&path)
+    } else {
+        Ok(DocumentMut::new())
+    }
+}
+
+pub fn fav(runtime: &RuntimeConfig, args: &MarkArgs) -> Result<(), String> {
+    if args.id.is_none() && !args.last {
+        return list_favorites(runtime);
+    }
+    let (_, quote, shown) = marked_quote(runtime, args)?;
+    let key = quote.short_id(16);
+    let path = favorites_file();
+    let mut doc = read_favorites()?;
+    let tables = doc
+        .entry("quote")
+        .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+        .ok_or_else(|| format!("'quote' is not an array of tables in {}", path.display()))?;
+    let found = find_table(tables, Path::new(FAVORITES_MODE), &key).ok();
+
+    match (found, args.remove) {
+        (Some(index), true) => {
+            tables.remove(index);
+            println!("Removed {shown} from the favorites");
+            if tables.is_empty() {
+                return fs::remove_file(&path)
+                    .map_err(|e| format!("failed to remove {}: {e}", path.display()));
+            }
+        }
+        (None, true) => return Err(format!("{shown} is not a favorite")),
+        (Some(_), false) => {
+            println!("{shown} is already a favorite");
+            return Ok(());
+        }
+        (None, false) => {
+            let mut table = quote_table(&quote);
+            if !tables.is_empty() {
+                table.decor_mut().set_prefix("\n");
+            }
+            tables.push(table);
+            println!("Added {shown} to the favorites");
+        }
+    }
+    fs::create_dir_all(state_dir())
+        .map_err(|e| format!("failed to create {}: {e}", state_dir().display()))?;
+    write_document(&path, &doc)
+}
+
+fn list_favorites(runtime: &RuntimeConfig) -> Result<(), String> {
+    if !favorites_file().exists() {
+        println!("No favorites yet, add the last quote shown with `kotofetch fav --last`");
+        return Ok(());
+    }
+    let args = crate::cli::ListArgs {
+        pack: Some(vec![PathBuf::from(FAVORITES_MODE)]),
+    };
+    crate::manage::list(runtime, &args)
+}
+
+pub fn hide(runtime: &RuntimeConfig, args: &MarkArgs) -> Result<(), String> {
+    let content = fs::read_to_string(state_dir().join("hidden")).unwrap_or_default();
+    if args.id.is_none() && !args.last {
+        if content.trim().is_empty() {
+            println!("No hidden quotes");
+        }
+        print!("{content}");
+        return Ok(());
+    }
+    let (pack, quote, shown) = marked_quote(runtime, args)?;
+    let full = full_id(&pack, &quote);
+    let hidden = hidden_ids(&content).any(|id| id == full);
+
+    let mut lines: Vec<&str> = content.lines().collect();
+    let line;
+    match (hidden, args.remove) {
+        (true, true) => {
+            lines.retain(|l| l.split_whitespace().next() != Some(&full));
+            println!("{shown} is shown again");
+        }
+        (false, true) => return Err(format!("{shown} is not hidden")),
+        (true, false) => {
+            println!("{shown} is already hidden");
+            return Ok(());
+        }
+        (false, false) => {
+            let japanese = quote.japanese.lines().next().unwrap_or_default();
+            line = format!("{full}  {}", truncate_to_width(japanese, 40));
+            lines.push(&line);
+            println!("Hid {shown}, it won't be shown again");
+        }
+    }
+    let mut content = lines.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    write_state("hidden", &content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(japanese: &str) -> Quote {
+        Quote {
+            japanese: japanese.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hidden_ids_are_the_first_word_of_each_line() {
+        let content = "anime:3f9a0c1e22aa77bd  猫に小判\n\n  haiku:ichigo\t一期一会\n/tmp/p.toml:0011223344556677\n";
+        let ids: Vec<&str> = hidden_ids(content).collect();
+        assert_eq!(
+            ids,
+            [
+                "anime:3f9a0c1e22aa77bd",
+                "haiku:ichigo",
+                "/tmp/p.toml:0011223344556677"
+            ]
+        );
+        assert_eq!(hidden_ids("").count(), 0);
+    }
+
+    #[test]
+    fn hidden_quotes_stay_hidden_in_favorites() {
+        let cat = quote("猫に小判");
+        let dog = quote("犬も歩けば棒に当たる");
+        let anime = Path::new("anime");
+        let favorites = Path::new(FAVORITES_MODE);
+
+        let hidden = Hidden::parse(&format!("{}  猫に小判\n", full_id(anime, &cat)));
+        assert!(hidden.contains(anime, &cat));
+        assert!(hidden.contains(favorites, &cat));
+        assert!(!hidden.contains(favorites, &dog));
+        assert!(!hidden.contains(Path::new("haiku"), &dog));
+
+        // hidden from the favorites, it is hidden where it comes from too
+        let hidden = Hidden::parse(&format!("{}\n", full_id(favorites, &dog)));
+        assert!(hidden.contains(favorites, &dog));
+        assert!(hidden.contains(anime, &dog));
+        assert!(!hidden.contains(anime, &cat));
+
+        // explicit IDs are copied with the quote
+        let ichigo = Quote {
+            id: Some("ichigo".to_string()),
+            ..quote("一期一会")
+        };
+        let hidden = Hidden::parse("haiku:ichigo\n");
+        assert!(hidden.contains(favorites, &ichigo));
+    }
+}